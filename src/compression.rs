@@ -0,0 +1,148 @@
+//! Compression of stored documents.
+//!
+//! Enabled with [`HtmlSaverBuilder::compression`](crate::HtmlSaverBuilder::compression).
+//! Each codec lives behind its own cargo feature (`gzip`, `zstd`, `brotli`).
+//! The worker compresses the sanitized content, appends the codec's file
+//! extension to the storage key (e.g. `page.html.gz`) and passes the matching
+//! `Content-Encoding` to the storage backend in
+//! [`ObjectMetadata::content_encoding`](crate::ObjectMetadata::content_encoding).
+
+use std::io;
+
+/// Compression applied to every stored document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Store content as-is.
+    #[default]
+    None,
+    /// gzip with the given level (`0..=9`). Requires the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip { level: u32 },
+    /// Zstandard with the given level (`1..=22`). Requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+    /// Brotli with the given quality (`0..=11`). Requires the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli { quality: u32 },
+}
+
+impl Compression {
+    /// gzip at the default level 6.
+    #[cfg(feature = "gzip")]
+    pub fn gzip() -> Self {
+        Self::Gzip { level: 6 }
+    }
+
+    /// Zstandard at the default level 3.
+    #[cfg(feature = "zstd")]
+    pub fn zstd() -> Self {
+        Self::Zstd { level: 3 }
+    }
+
+    /// Brotli at quality 5, a good trade-off for large pages.
+    #[cfg(feature = "brotli")]
+    pub fn brotli() -> Self {
+        Self::Brotli { quality: 5 }
+    }
+
+    /// Value of the `Content-Encoding` header for compressed content.
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            #[cfg(feature = "gzip")]
+            Self::Gzip { .. } => Some("gzip"),
+            #[cfg(feature = "zstd")]
+            Self::Zstd { .. } => Some("zstd"),
+            #[cfg(feature = "brotli")]
+            Self::Brotli { .. } => Some("br"),
+        }
+    }
+
+    /// Suffix appended to the storage key, including the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            #[cfg(feature = "gzip")]
+            Self::Gzip { .. } => ".gz",
+            #[cfg(feature = "zstd")]
+            Self::Zstd { .. } => ".zst",
+            #[cfg(feature = "brotli")]
+            Self::Brotli { .. } => ".br",
+        }
+    }
+
+    /// Compress `content` with this codec.
+    pub fn compress(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            Self::None => Ok(content.to_vec()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip { level } => {
+                use std::io::Write;
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(content.len() / 4),
+                    flate2::Compression::new(level.min(9)),
+                );
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd { level } => zstd::bulk::compress(content, level),
+            #[cfg(feature = "brotli")]
+            Self::Brotli { quality } => {
+                let mut out = Vec::with_capacity(content.len() / 4);
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: quality.min(11) as i32,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &content[..], &mut out, &params)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &[u8] = b"<html><body><p>hello hello hello hello hello</p></body></html>";
+
+    #[test]
+    fn none_is_passthrough() {
+        let c = Compression::None;
+        assert_eq!(c.compress(HTML).unwrap(), HTML);
+        assert_eq!(c.content_encoding(), None);
+        assert_eq!(c.extension(), "");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        use std::io::Read;
+        let compressed = Compression::gzip().compress(HTML).unwrap();
+        let mut out = Vec::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, HTML);
+        assert_eq!(Compression::gzip().content_encoding(), Some("gzip"));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let compressed = Compression::zstd().compress(HTML).unwrap();
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), HTML);
+        assert_eq!(Compression::zstd().extension(), ".zst");
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn brotli_round_trip() {
+        let compressed = Compression::brotli().compress(HTML).unwrap();
+        let mut out = Vec::new();
+        brotli::BrotliDecompress(&mut &compressed[..], &mut out).unwrap();
+        assert_eq!(out, HTML);
+        assert_eq!(Compression::brotli().content_encoding(), Some("br"));
+    }
+}
@@ -1,22 +1,36 @@
 //! Builder for configuring and launching the background HTML-saving worker.
 
+use std::sync::Arc;
 use std::time::Duration;
 
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::compression::Compression;
+use crate::dedup::{Dedup, DedupConfig};
+use crate::error::{HtmlSaverError, Result};
 use crate::handle::HtmlSaverHandle;
-use crate::sanitizer::{Sanitizer, SanitizerPipeline};
+use crate::key::{self, KeyPolicy};
+use crate::overflow::{Overflow, OverflowPolicy, Spill};
+use crate::overwrite::OverwritePolicy;
+use crate::retry::RetryPolicy;
+use crate::sanitizer::executor::Runner;
+use crate::sanitizer::{Sanitizer, SanitizerExecutor, SanitizerPipeline};
 use crate::saveable::Saveable;
-use crate::storage::Storage;
-use crate::worker;
+use crate::storage::{DynStorage, Storage};
+use crate::template::KeyTemplate;
+use crate::wal::{Wal, WalConfig};
+use crate::worker::{self, Preparer, SharedReceiver, WorkerConfig};
 
 /// Builder for configuring and starting an [`HtmlSaverHandle`].
 ///
-/// Provides a fluent API for setting batch size, flush interval, channel
-/// buffer capacity, storage key prefix, and the sanitizer pipeline.
+/// Provides a fluent API for setting batch size, upload concurrency, flush
+/// interval, channel buffer capacity, storage key prefix, retry policy, and
+/// the sanitizer pipeline.
 ///
 /// # Example
 ///
 /// ```rust,no_run
-/// use html_saver::{HtmlSaverBuilder, FsStorage, Saveable, SubstringSanitizer};
+/// use html_saver::{HtmlSaverBuilder, FsStorage, RetryPolicy, Saveable, SubstringSanitizer};
 /// use std::time::Duration;
 ///
 /// # struct MyRequest;
@@ -27,9 +41,11 @@ use crate::worker;
 /// # async fn example() {
 /// let handle = HtmlSaverBuilder::new(FsStorage::new("/tmp/html"))
 ///     .batch_size(100)
+///     .max_concurrent_uploads(8)
 ///     .flush_interval(Duration::from_secs(10))
 ///     .channel_buffer(5000)
 ///     .prefix("snapshots/v1")
+///     .retry_policy(RetryPolicy::new().max_attempts(5))
 ///     .add_sanitizer(SubstringSanitizer::new(vec![("secret", "***")]))
 ///     .build::<MyRequest>();
 /// # }
@@ -37,25 +53,61 @@ use crate::worker;
 pub struct HtmlSaverBuilder<S: Storage> {
     storage: S,
     batch_size: usize,
+    max_concurrent_uploads: usize,
     flush_interval: Duration,
     channel_buffer: usize,
+    sanitize_buffer: usize,
+    upload_buffer: usize,
     sanitizers: SanitizerPipeline,
+    sanitizer_executor: SanitizerExecutor,
+    sanitizer_parallelism: usize,
     prefix: String,
+    key_template: Option<String>,
+    key_policy: KeyPolicy,
+    compression: Compression,
+    retry: RetryPolicy,
+    overwrite: OverwritePolicy,
+    dedup: Option<DedupConfig>,
+    dead_letter: Option<Box<dyn DynStorage>>,
+    wal: Option<WalConfig>,
+    overflow: OverflowPolicy,
 }
 
 impl<S: Storage> HtmlSaverBuilder<S> {
     /// Create a new builder with the given storage backend and sensible defaults.
     ///
-    /// Defaults: batch size 50, flush interval 5 s, channel buffer 1000,
-    /// no sanitizers, no prefix.
+    /// Defaults:
+    ///
+    /// - batch size 50, 16 concurrent uploads, flush interval 5 s;
+    /// - channel buffer 1000, sanitize and upload buffers of 64;
+    /// - no sanitizers, run on the blocking thread pool with one item per
+    ///   available CPU;
+    /// - no prefix or key template, invalid keys rejected;
+    /// - existing objects overwritten, no deduplication, no compression;
+    /// - no retries, no dead-letter sink, in-memory queue only;
+    /// - items rejected when the channel is full.
     pub fn new(storage: S) -> Self {
         Self {
             storage,
             batch_size: 50,
+            max_concurrent_uploads: 16,
             flush_interval: Duration::from_secs(5),
             channel_buffer: 1000,
+            sanitize_buffer: 64,
+            upload_buffer: 64,
             sanitizers: SanitizerPipeline::new(),
+            sanitizer_executor: SanitizerExecutor::Blocking,
+            sanitizer_parallelism: std::thread::available_parallelism().map_or(1, |n| n.get()),
             prefix: String::new(),
+            key_template: None,
+            key_policy: KeyPolicy::Reject,
+            compression: Compression::None,
+            retry: RetryPolicy::none(),
+            overwrite: OverwritePolicy::Overwrite,
+            dedup: None,
+            dead_letter: None,
+            wal: None,
+            overflow: OverflowPolicy::Reject,
         }
     }
 
@@ -65,6 +117,17 @@ impl<S: Storage> HtmlSaverBuilder<S> {
         self
     }
 
+    /// Maximum number of uploads in flight at once, independent of the batch
+    /// size. Values below 1 are treated as 1.
+    ///
+    /// Items that do not get an upload slot right away wait in the
+    /// [upload buffer](Self::upload_buffer) while the worker keeps receiving
+    /// and sanitizing new ones.
+    pub fn max_concurrent_uploads(mut self, limit: usize) -> Self {
+        self.max_concurrent_uploads = limit.max(1);
+        self
+    }
+
     /// Time interval after which the batch is flushed regardless of size.
     pub fn flush_interval(mut self, interval: Duration) -> Self {
         self.flush_interval = interval;
@@ -77,6 +140,26 @@ impl<S: Storage> HtmlSaverBuilder<S> {
         self
     }
 
+    /// Capacity of the queue between the batching stage of the worker and the
+    /// sanitize stage.
+    ///
+    /// Flushed batches wait here while the sanitizers are busy; once it is
+    /// full, the worker stops taking items off the channel.
+    pub fn sanitize_buffer(mut self, size: usize) -> Self {
+        self.sanitize_buffer = size;
+        self
+    }
+
+    /// Capacity of the queue between the sanitize stage of the worker and the
+    /// upload stage.
+    ///
+    /// Sanitized items wait here for a free upload slot (see
+    /// [`max_concurrent_uploads`](Self::max_concurrent_uploads)).
+    pub fn upload_buffer(mut self, size: usize) -> Self {
+        self.upload_buffer = size;
+        self
+    }
+
     /// Append a [`Sanitizer`] to the processing pipeline.
     ///
     /// Sanitizers run in the order they are added, each receiving the output
@@ -86,28 +169,216 @@ impl<S: Storage> HtmlSaverBuilder<S> {
         self
     }
 
+    /// Choose where the sanitizer pipeline runs. See [`SanitizerExecutor`].
+    pub fn sanitizer_executor(mut self, executor: SanitizerExecutor) -> Self {
+        self.sanitizer_executor = executor;
+        self
+    }
+
+    /// Maximum number of items sanitized at the same time. Values below 1
+    /// are treated as 1.
+    ///
+    /// With [`SanitizerExecutor::Dedicated`] this is also the number of
+    /// threads in the pool. [`SanitizerExecutor::Inline`] always sanitizes
+    /// one item at a time.
+    pub fn sanitizer_parallelism(mut self, parallelism: usize) -> Self {
+        self.sanitizer_parallelism = parallelism.max(1);
+        self
+    }
+
     /// Set a prefix that is prepended to every storage key (separated by `/`).
     pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
         self.prefix = prefix.into();
         self
     }
 
+    /// Build keys from a [template](crate::template) such as
+    /// `{date:%Y/%m/%d}/{host}/{name}` instead of [`Saveable::name`] alone.
+    ///
+    /// The template is parsed by [`try_build`](Self::try_build); the prefix,
+    /// if any, is still prepended to the rendered key.
+    pub fn key_template(mut self, template: impl Into<String>) -> Self {
+        self.key_template = Some(template.into());
+        self
+    }
+
+    /// Choose what happens to items whose key is not a safe relative path,
+    /// e.g. `../../etc/passwd`. See [`KeyPolicy`] and the [`key`](crate::key)
+    /// module for the rules.
+    pub fn key_policy(mut self, policy: KeyPolicy) -> Self {
+        self.key_policy = policy;
+        self
+    }
+
+    /// Compress stored documents with the given [`Compression`].
+    ///
+    /// The codec's extension is appended to every key (e.g. `page.html.gz`)
+    /// and its `Content-Encoding` is passed to the storage backend.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Retry failed uploads according to the given [`RetryPolicy`].
+    ///
+    /// Each item in a batch is retried independently.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Choose what happens when an object already exists under an item's
+    /// key: overwrite it, skip the item or store it under a suffixed key.
+    /// See [`OverwritePolicy`].
+    pub fn overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Store identical content once, under a key derived from its hash, and
+    /// write a small [manifest](crate::dedup::Manifest) pointing at it under
+    /// each item's key. See the [`dedup`](crate::dedup) module.
+    pub fn deduplicate(mut self, config: DedupConfig) -> Self {
+        self.dedup = Some(config);
+        self
+    }
+
+    /// Send items whose upload failed permanently to a secondary storage.
+    ///
+    /// The sanitized content is written under its original key, next to a
+    /// [`DeadLetterRecord`](crate::dead_letter::DeadLetterRecord) sidecar. Use
+    /// [`dead_letter::replay`](crate::dead_letter::replay) to push a local spool
+    /// directory back to the primary storage.
+    pub fn dead_letter(mut self, storage: impl Storage) -> Self {
+        self.dead_letter = Some(Box::new(storage));
+        self
+    }
+
+    /// Journal queued items to a durable on-disk [write-ahead log](crate::wal).
+    ///
+    /// Items are appended to the log before `save` returns and acknowledged
+    /// once stored. Unacknowledged items left over from a previous run are
+    /// replayed when the saver is built.
+    pub fn write_ahead_log(mut self, config: WalConfig) -> Self {
+        self.wal = Some(config);
+        self
+    }
+
+    /// Choose what happens when the channel is full.
+    ///
+    /// Applies to the non-blocking `save`, `save_with_ack` and `save_or_log`
+    /// methods. See [`OverflowPolicy`] for the options.
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
     /// Consume the builder, spawn the background worker, and return the
     /// [`HtmlSaverHandle`] used to submit items and control the worker lifecycle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid. Use [`try_build`](Self::try_build)
+    /// for a fallible alternative.
     pub fn build<R: Saveable>(self) -> HtmlSaverHandle<R> {
-        let (tx, rx) = tokio::sync::mpsc::channel::<R>(self.channel_buffer);
+        self.try_build()
+            .unwrap_or_else(|e| panic!("Failed to build HtmlSaver: {e}"))
+    }
+
+    /// Fallible variant of [`build`](Self::build).
+    ///
+    /// Returns [`HtmlSaverError::Wal`] if the write-ahead log cannot be opened,
+    /// [`HtmlSaverError::Spill`] if the spill directory cannot be created and
+    /// [`HtmlSaverError::Config`] if the prefix or the blob prefix of
+    /// [deduplication](Self::deduplicate) is not a valid key, the key template
+    /// is invalid, the sanitizer threads cannot be started or
+    /// [`OverflowPolicy::Block`] is set on a current-thread runtime.
+    pub fn try_build<R: Saveable>(self) -> Result<HtmlSaverHandle<R>> {
+        if !self.prefix.is_empty() {
+            key::validate_key(&self.prefix)
+                .map_err(|e| HtmlSaverError::Config(format!("prefix: {e}")))?;
+        }
+        let key_template = self
+            .key_template
+            .as_deref()
+            .map(KeyTemplate::parse)
+            .transpose()?;
+        if self.overflow == OverflowPolicy::Block
+            && Handle::try_current()
+                .is_ok_and(|rt| rt.runtime_flavor() == RuntimeFlavor::CurrentThread)
+        {
+            return Err(HtmlSaverError::Config(
+                "overflow policy Block needs a multi-threaded runtime".to_string(),
+            ));
+        }
+        if let Some(dedup) = &self.dedup {
+            key::validate_key(dedup.prefix())
+                .map_err(|e| HtmlSaverError::Config(format!("blob prefix: {e}")))?;
+        }
+
+        let sanitizer_parallelism = match self.sanitizer_executor {
+            SanitizerExecutor::Inline => 1,
+            _ => self.sanitizer_parallelism,
+        };
+        let sanitizer_runner = Runner::new(self.sanitizer_executor, sanitizer_parallelism)?;
+
+        let (wal, recovered) = match self.wal {
+            Some(config) => {
+                let (wal, recovered) = Wal::open(config).map_err(HtmlSaverError::Wal)?;
+                (Some(Arc::new(wal)), recovered)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let spill = match &self.overflow {
+            OverflowPolicy::SpillToDisk(dir) => {
+                let spill = Spill::open(dir, self.retry.clone()).map_err(HtmlSaverError::Spill)?;
+                Some(Arc::new(spill))
+            }
+            _ => None,
+        };
+
+        let (tx, rx) = tokio::sync::mpsc::channel(self.channel_buffer);
+        let rx = SharedReceiver::new(rx);
+        let overflow = Overflow::new(self.overflow, spill.clone(), &rx);
         let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
         let worker_handle = tokio::spawn(worker::run(
             rx,
             shutdown_rx,
-            self.storage,
-            self.sanitizers,
-            self.prefix,
-            self.batch_size,
-            self.flush_interval,
+            WorkerConfig {
+                storage: self.storage,
+                preparer: Arc::new(Preparer {
+                    sanitizers: self.sanitizers,
+                    prefix: self.prefix,
+                    key_policy: self.key_policy,
+                    compression: self.compression,
+                    spill: spill.clone(),
+                }),
+                sanitizer_runner,
+                sanitizer_parallelism,
+                batch_size: self.batch_size,
+                max_concurrent_uploads: self.max_concurrent_uploads,
+                sanitize_buffer: self.sanitize_buffer,
+                upload_buffer: self.upload_buffer,
+                flush_interval: self.flush_interval,
+                retry: self.retry,
+                overwrite: self.overwrite,
+                dedup: self.dedup.map(Dedup::new),
+                dead_letter: self.dead_letter,
+                wal: wal.clone(),
+                spill,
+            },
+            recovered,
         ));
 
-        HtmlSaverHandle::new(tx, shutdown_tx, worker_handle)
+        Ok(HtmlSaverHandle::new(
+            tx,
+            wal,
+            overflow,
+            key_template,
+            shutdown_tx,
+            worker_handle,
+        ))
     }
 }
@@ -0,0 +1,184 @@
+//! Dead-letter sink for items whose upload failed permanently.
+//!
+//! When a dead-letter storage is configured via
+//! [`HtmlSaverBuilder::dead_letter`](crate::HtmlSaverBuilder::dead_letter),
+//! every item that exhausts its [`RetryPolicy`](crate::RetryPolicy) is written
+//! to it under its original key, together with a
+//! `<key>.deadletter.json` sidecar describing the failure. A local
+//! [`FsStorage`](crate::FsStorage) spool directory can later be pushed back to
+//! the primary storage with [`replay`].
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::storage::{DynStorage, FsStorage, ObjectMetadata, Storage};
+
+/// Suffix appended to a key to form the name of its sidecar file.
+pub const SIDECAR_SUFFIX: &str = ".deadletter.json";
+
+/// Contents of the sidecar written next to every dead-lettered item.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadLetterRecord {
+    /// Storage key the item was meant to be written under.
+    pub key: String,
+    /// Metadata the item was uploaded with, stored as top-level fields.
+    #[serde(flatten)]
+    pub metadata: ObjectMetadata,
+    /// Display form of the last upload error.
+    pub error: String,
+    /// Number of upload attempts made before giving up.
+    pub attempts: u32,
+    /// Time of the final failure, in seconds since the Unix epoch.
+    pub failed_at: u64,
+}
+
+impl DeadLetterRecord {
+    pub(crate) fn new(
+        key: &str,
+        metadata: &ObjectMetadata,
+        error: &HtmlSaverError,
+        attempts: u32,
+    ) -> Self {
+        Self {
+            key: key.to_string(),
+            metadata: metadata.clone(),
+            error: error.to_string(),
+            attempts,
+            failed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Write an item and its sidecar to the dead-letter storage.
+pub(crate) async fn write(
+    storage: &dyn DynStorage,
+    record: &DeadLetterRecord,
+    content: &[u8],
+) -> Result<()> {
+    let sidecar =
+        serde_json::to_vec_pretty(record).map_err(|e| HtmlSaverError::StorageUpload(e.into()))?;
+    storage
+        .put_dyn(&record.key, content, &record.metadata)
+        .await?;
+    storage
+        .put_dyn(
+            &format!("{}{SIDECAR_SUFFIX}", record.key),
+            &sidecar,
+            &ObjectMetadata::new("application/json"),
+        )
+        .await
+}
+
+/// Outcome of a [`replay`] run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Items successfully uploaded and removed from the spool directory.
+    pub replayed: usize,
+    /// Items that failed again and were left in place.
+    pub failed: usize,
+}
+
+/// Re-upload every item found in a dead-letter spool directory to `storage`.
+///
+/// The key of each item is its path relative to `dir`. Successfully replayed
+/// items are deleted from the spool together with their sidecars; failed items
+/// are left untouched so the replay can be repeated later. Sidecars and the
+/// temp files of interrupted [`FsStorage`] writes are skipped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use html_saver::{dead_letter, FsStorage};
+///
+/// # async fn example() -> html_saver::Result<()> {
+/// let primary = FsStorage::new("/var/data/html");
+/// let report = dead_letter::replay("/var/spool/html_saver", &primary).await?;
+/// println!("replayed {}, still failing {}", report.replayed, report.failed);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn replay<S: Storage>(dir: impl AsRef<Path>, storage: &S) -> Result<ReplayReport> {
+    let dir = dir.as_ref();
+    let mut report = ReplayReport::default();
+
+    for path in list_files(dir).await? {
+        let Some(name) = path.to_str() else {
+            continue;
+        };
+        // Temp files are left behind by dead-letter writes that crashed.
+        if name.ends_with(SIDECAR_SUFFIX)
+            || name.ends_with(FsStorage::METADATA_SUFFIX)
+            || name.ends_with(FsStorage::TEMP_SUFFIX)
+        {
+            continue;
+        }
+        let Some(key) = path
+            .strip_prefix(dir)
+            .ok()
+            .and_then(|p| p.to_str())
+            .map(|k| k.replace(std::path::MAIN_SEPARATOR, "/"))
+        else {
+            continue;
+        };
+
+        let sidecar_path = PathBuf::from(format!("{name}{SIDECAR_SUFFIX}"));
+        let metadata = match tokio::fs::read(&sidecar_path).await {
+            Ok(bytes) => serde_json::from_slice::<DeadLetterRecord>(&bytes)
+                .map(|r| r.metadata)
+                .unwrap_or_else(|_| ObjectMetadata::new("text/html")),
+            Err(_) => ObjectMetadata::new("text/html"),
+        };
+
+        let content = tokio::fs::read(&path).await.map_err(io_error)?;
+        match storage.put(&key, &content, &metadata).await {
+            Ok(()) => {
+                tokio::fs::remove_file(&path).await.map_err(io_error)?;
+                let _ = tokio::fs::remove_file(&sidecar_path).await;
+                // A spool written by FsStorage may hold metadata sidecars of both.
+                for file in [&path, &sidecar_path] {
+                    let meta = format!("{}{}", file.display(), FsStorage::METADATA_SUFFIX);
+                    let _ = tokio::fs::remove_file(meta).await;
+                }
+                tracing::info!("Replayed dead-lettered item {key}");
+                report.replayed += 1;
+            }
+            Err(e) => {
+                tracing::warn!("Replay of {key} failed: {e}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Recursively collect all regular files below `dir`.
+async fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&current).await.map_err(io_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let file_type = entry.file_type().await.map_err(io_error)?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn io_error(e: std::io::Error) -> HtmlSaverError {
+    HtmlSaverError::StorageUpload(Box::new(e))
+}
@@ -0,0 +1,385 @@
+//! Content-addressed storage of identical documents.
+//!
+//! Enabled via [`HtmlSaverBuilder::deduplicate`](crate::HtmlSaverBuilder::deduplicate).
+//! The stored bytes of every item (sanitized and, if enabled, compressed) are
+//! written once under a key derived from their SHA-256, for example
+//! `blobs/0a/0a4735...e772`, and the item's own key receives a small JSON
+//! [`Manifest`] pointing at that blob. Refetching an unchanged page then costs
+//! a manifest write instead of a full copy.
+//!
+//! An in-memory LRU of recently written hashes skips the blob upload for
+//! content seen before, and an LRU of recently written manifests skips the
+//! manifest as well when a key is saved again with the same content, so an
+//! unchanged page costs no [`Storage::put`] at all. On a cache miss the blob
+//! is only uploaded if [`Storage::exists`] does not find it.
+//!
+//! Use [`read`] to fetch a document through its manifest.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::overwrite::OverwritePolicy;
+use crate::storage::{ObjectMetadata, Storage, StoredObject};
+use crate::template::hex_sha256;
+
+/// Content type of [`Manifest`] objects.
+pub const MANIFEST_CONTENT_TYPE: &str = "application/vnd.html-saver.manifest+json";
+
+/// Configuration for content-addressed deduplication.
+///
+/// # Example
+///
+/// ```
+/// use html_saver::DedupConfig;
+///
+/// let dedup = DedupConfig::new()
+///     .blob_prefix("content/sha256")
+///     .cache_capacity(100_000);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DedupConfig {
+    blob_prefix: String,
+    cache_capacity: usize,
+}
+
+impl DedupConfig {
+    /// Store blobs under `blobs/` and remember the 10 000 most recent hashes
+    /// and manifests.
+    pub fn new() -> Self {
+        Self {
+            blob_prefix: "blobs".to_string(),
+            cache_capacity: 10_000,
+        }
+    }
+
+    /// Key prefix of the content blobs. It is used as is; the builder's
+    /// [prefix](crate::HtmlSaverBuilder::prefix) is not added.
+    pub fn blob_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.blob_prefix = prefix.into();
+        self
+    }
+
+    /// Number of hashes, and of manifests, kept in the in-memory caches.
+    /// `0` disables them, so every item checks whether its blob exists.
+    pub fn cache_capacity(mut self, entries: usize) -> Self {
+        self.cache_capacity = entries;
+        self
+    }
+
+    pub(crate) fn prefix(&self) -> &str {
+        &self.blob_prefix
+    }
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The object stored under an item's key when deduplication is enabled.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Manifest {
+    /// Key of the blob holding the content.
+    pub blob: String,
+    /// Hex SHA-256 of the stored content.
+    pub sha256: String,
+    /// Size of the stored content in bytes.
+    pub size: u64,
+    /// MIME type of the (uncompressed) content.
+    pub content_type: String,
+    /// Encoding applied to the content, e.g. `"gzip"`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+}
+
+/// Read `key` from `storage`, following a [`Manifest`] to its blob.
+///
+/// Objects that are not manifests are returned as they are. Returns `None`
+/// if the key, or the blob its manifest points to, does not exist.
+pub async fn read<S: Storage>(storage: &S, key: &str) -> Result<Option<StoredObject>> {
+    let Some(object) = storage.get(key).await? else {
+        return Ok(None);
+    };
+    // Backends that do not keep metadata are given the benefit of the doubt.
+    let maybe_manifest = object
+        .metadata
+        .as_ref()
+        .is_none_or(|m| m.content_type == MANIFEST_CONTENT_TYPE);
+    if maybe_manifest && let Ok(manifest) = serde_json::from_slice::<Manifest>(&object.content) {
+        return storage.get(&manifest.blob).await;
+    }
+    Ok(Some(object))
+}
+
+/// Writes items as blobs plus manifests.
+pub(crate) struct Dedup {
+    blob_prefix: String,
+    caches: Mutex<Caches>,
+}
+
+struct Caches {
+    /// Hashes whose blob is known to exist.
+    blobs: Lru<()>,
+    /// Hash last written to each key, with the key the manifest ended up
+    /// under.
+    manifests: Lru<(String, String)>,
+}
+
+impl Dedup {
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            blob_prefix: config.blob_prefix,
+            caches: Mutex::new(Caches {
+                blobs: Lru::new(config.cache_capacity),
+                manifests: Lru::new(config.cache_capacity),
+            }),
+        }
+    }
+
+    /// Store `content` as a blob and a manifest under `key` according to
+    /// `overwrite`, returning the key the manifest ends up under.
+    pub async fn put<S: Storage>(
+        &self,
+        storage: &S,
+        overwrite: &OverwritePolicy,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<String> {
+        let hash = hex_sha256(content);
+        let unchanged = self
+            .caches()
+            .manifests
+            .get(key)
+            .filter(|(written, _)| *written == hash)
+            .map(|(_, stored)| stored.clone());
+        if let Some(stored) = unchanged {
+            tracing::debug!("Skipped {key}: unchanged since it was stored as {stored}");
+            return Ok(stored);
+        }
+
+        let blob = format!("{}/{}/{hash}", self.blob_prefix, &hash[..2]);
+        let cached = self.caches().blobs.get(&hash).is_some();
+        if !cached {
+            // Blobs are shared between items, so only the metadata describing
+            // the bytes goes on them; the rest stays on the manifest.
+            let mut blob_metadata = ObjectMetadata::new(metadata.content_type.clone());
+            blob_metadata.content_encoding = metadata.content_encoding.clone();
+            // Backends that cannot tell get the blob written again.
+            if !matches!(storage.exists(&blob).await, Ok(true)) {
+                storage.put(&blob, content, &blob_metadata).await?;
+            }
+            self.caches().blobs.insert(hash.clone(), ());
+        }
+
+        let manifest = Manifest {
+            blob,
+            sha256: hash.clone(),
+            size: content.len() as u64,
+            content_type: metadata.content_type.clone(),
+            content_encoding: metadata.content_encoding.clone(),
+        };
+        let body = serde_json::to_vec(&manifest).map_err(|e| HtmlSaverError::Storage(e.into()))?;
+        let mut manifest_metadata = metadata.clone();
+        manifest_metadata.content_type = MANIFEST_CONTENT_TYPE.to_string();
+        manifest_metadata.content_encoding = None;
+
+        let stored = overwrite
+            .put(storage, key, &body, &manifest_metadata)
+            .await?;
+        self.caches()
+            .manifests
+            .insert(key.to_string(), (hash, stored.clone()));
+        Ok(stored)
+    }
+
+    /// The caches are never held across an `.await`.
+    fn caches(&self) -> std::sync::MutexGuard<'_, Caches> {
+        self.caches.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A least-recently-used map from strings.
+struct Lru<V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, V)>,
+    /// Keys by the tick they were last used at.
+    order: BTreeMap<u64, String>,
+}
+
+impl<V> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&V> {
+        self.tick += 1;
+        let (used, value) = self.entries.get_mut(key)?;
+        if let Some(key) = self.order.remove(used) {
+            self.order.insert(self.tick, key);
+        }
+        *used = self.tick;
+        Some(value)
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((used, _)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (self.tick, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::FsStorage;
+
+    use super::*;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used_entry() {
+        let mut lru = Lru::new(2);
+        lru.insert("a".into(), 1);
+        lru.insert("b".into(), 2);
+        assert_eq!(lru.get("a"), Some(&1));
+        lru.insert("c".into(), 3);
+
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("a"), Some(&1));
+        assert_eq!(lru.get("c"), Some(&3));
+
+        let mut disabled = Lru::new(0);
+        disabled.insert("a".into(), 1);
+        assert_eq!(disabled.get("a"), None);
+    }
+
+    #[tokio::test]
+    async fn identical_content_is_stored_once() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let dedup = Dedup::new(DedupConfig::new());
+        let overwrite = OverwritePolicy::Overwrite;
+
+        for key in ["a.html", "b.html"] {
+            let stored = dedup
+                .put(&storage, &overwrite, key, b"<p>same</p>", &html())
+                .await
+                .unwrap();
+            assert_eq!(stored, key);
+        }
+
+        let hash = hex_sha256(b"<p>same</p>");
+        let blob = tmp.path().join("blobs").join(&hash[..2]).join(&hash);
+        assert_eq!(std::fs::read(blob).unwrap(), b"<p>same</p>");
+
+        let manifest: Manifest =
+            serde_json::from_slice(&std::fs::read(tmp.path().join("b.html")).unwrap()).unwrap();
+        assert_eq!(manifest.sha256, hash);
+        assert_eq!(manifest.size, 11);
+        assert_eq!(manifest.content_type, "text/html");
+
+        let object = read(&storage, "a.html").await.unwrap().unwrap();
+        assert_eq!(object.content, b"<p>same</p>");
+        assert!(read(&storage, "missing.html").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn item_metadata_stays_on_the_manifest() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path()).write_metadata(true);
+        let dedup = Dedup::new(DedupConfig::new());
+        let metadata = html()
+            .content_encoding("gzip")
+            .cache_control("max-age=60")
+            .user_metadata("source-url", "https://example.com/a")
+            .tag("crawl", "daily");
+        dedup
+            .put(
+                &storage,
+                &OverwritePolicy::Overwrite,
+                "a.html",
+                b"x",
+                &metadata,
+            )
+            .await
+            .unwrap();
+
+        let hash = hex_sha256(b"x");
+        let blob = storage
+            .get(&format!("blobs/{}/{hash}", &hash[..2]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(blob.metadata, Some(html().content_encoding("gzip")));
+        let manifest = storage
+            .get("a.html")
+            .await
+            .unwrap()
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(manifest.content_type, MANIFEST_CONTENT_TYPE);
+        assert_eq!(manifest.user_metadata, metadata.user_metadata);
+        assert_eq!(manifest.tags, metadata.tags);
+        assert_eq!(manifest.cache_control, metadata.cache_control);
+    }
+
+    #[tokio::test]
+    async fn unchanged_keys_are_not_written_again() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let dedup = Dedup::new(DedupConfig::new().blob_prefix("cas"));
+        let overwrite = OverwritePolicy::Overwrite;
+
+        dedup
+            .put(&storage, &overwrite, "page.html", b"v1", &html())
+            .await
+            .unwrap();
+        std::fs::remove_file(tmp.path().join("page.html")).unwrap();
+
+        // Cached: neither the manifest nor the blob is written.
+        dedup
+            .put(&storage, &overwrite, "page.html", b"v1", &html())
+            .await
+            .unwrap();
+        assert!(!tmp.path().join("page.html").exists());
+
+        // Changed content is written.
+        dedup
+            .put(&storage, &overwrite, "page.html", b"v2", &html())
+            .await
+            .unwrap();
+        let object = read(&storage, "page.html").await.unwrap().unwrap();
+        assert_eq!(object.content, b"v2");
+        assert_eq!(
+            std::fs::read_dir(tmp.path().join("cas")).unwrap().count(),
+            2
+        );
+    }
+}
@@ -1,5 +1,7 @@
 //! Error types for the `html_saver` crate.
 
+use std::fmt;
+
 /// All errors that can occur during HTML saving operations.
 #[derive(Debug, thiserror::Error)]
 pub enum HtmlSaverError {
@@ -7,14 +9,48 @@ pub enum HtmlSaverError {
     #[error("Storage upload failed: {0}")]
     StorageUpload(Box<dyn std::error::Error + Send + Sync>),
 
-    /// The internal channel to the background worker is closed or full.
-    #[error("Channel closed or full")]
+    /// A storage backend failed to read, list or delete content.
+    #[error("Storage operation failed: {0}")]
+    Storage(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The storage backend does not support the requested operation.
+    #[error("Storage operation not supported: {0}")]
+    Unsupported(&'static str),
+
+    /// A storage key is not a safe relative path; see [`key`](crate::key).
+    #[error("Invalid key {key:?}: {reason}")]
+    InvalidKey {
+        /// The rejected key.
+        key: String,
+        /// Why the key was rejected.
+        reason: &'static str,
+    },
+
+    /// The internal channel to the background worker is full.
+    #[error("Channel full")]
+    ChannelFull,
+
+    /// The background worker has stopped and no longer accepts items.
+    #[error("Channel closed")]
     ChannelClosed,
 
+    /// The item was discarded by the configured
+    /// [`OverflowPolicy`](crate::OverflowPolicy) before it was stored.
+    #[error("Item dropped by overflow policy")]
+    Dropped,
+
+    /// The spill directory of the overflow policy could not be opened.
+    #[error("Spill directory error: {0}")]
+    Spill(std::io::Error),
+
     /// A sanitizer encountered an error while processing HTML.
     #[error("Sanitizer error: {0}")]
     Sanitizer(String),
 
+    /// The write-ahead log could not be read or written.
+    #[error("Write-ahead log error: {0}")]
+    Wal(std::io::Error),
+
     /// The builder configuration is invalid.
     #[error("Config error: {0}")]
     Config(String),
@@ -22,3 +58,68 @@ pub enum HtmlSaverError {
 
 /// A type alias for `Result<T, HtmlSaverError>`.
 pub type Result<T> = std::result::Result<T, HtmlSaverError>;
+
+/// Error returned when an item could not be queued for saving.
+///
+/// Every variant hands the rejected item back so the caller can retry it,
+/// spill it elsewhere, or account for the drop.
+#[derive(thiserror::Error)]
+pub enum SaveError<R> {
+    /// The channel is at capacity (or stayed full for the whole timeout).
+    #[error("Channel full")]
+    Full(R),
+
+    /// The background worker has stopped.
+    #[error("Channel closed")]
+    Closed(R),
+
+    /// The item could not be appended to the write-ahead log.
+    #[error("Write-ahead log error: {1}")]
+    Wal(R, std::io::Error),
+
+    /// The [key template](crate::HtmlSaverBuilder::key_template) could not be
+    /// rendered for the item, usually because it lacks a field.
+    #[error("Key error: {1}")]
+    Key(R, HtmlSaverError),
+}
+
+impl<R> SaveError<R> {
+    /// Take back the item that could not be queued.
+    pub fn into_inner(self) -> R {
+        match self {
+            Self::Full(item) | Self::Closed(item) | Self::Wal(item, _) | Self::Key(item, _) => item,
+        }
+    }
+
+    /// Returns `true` if the item was rejected because the channel is full.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Returns `true` if the item was rejected because the worker has stopped.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+}
+
+impl<R> fmt::Debug for SaveError<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("Full(..)"),
+            Self::Closed(_) => f.write_str("Closed(..)"),
+            Self::Wal(_, e) => f.debug_tuple("Wal").field(&"..").field(e).finish(),
+            Self::Key(_, e) => f.debug_tuple("Key").field(&"..").field(e).finish(),
+        }
+    }
+}
+
+impl<R> From<SaveError<R>> for HtmlSaverError {
+    fn from(e: SaveError<R>) -> Self {
+        match e {
+            SaveError::Full(_) => Self::ChannelFull,
+            SaveError::Closed(_) => Self::ChannelClosed,
+            SaveError::Wal(_, e) => Self::Wal(e),
+            SaveError::Key(_, e) => e,
+        }
+    }
+}
@@ -1,10 +1,23 @@
 //! Handles for submitting save requests and controlling the background worker.
 
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
 
-use crate::error::{HtmlSaverError, Result};
+use crate::error::{HtmlSaverError, SaveError};
+use crate::overflow::{Overflow, OverflowPolicy, OverflowStats};
 use crate::saveable::Saveable;
+use crate::template::KeyTemplate;
+use crate::wal::Wal;
+use crate::worker::Queued;
 
 /// Primary handle returned by [`HtmlSaverBuilder::build`](crate::HtmlSaverBuilder::build).
 ///
@@ -15,19 +28,27 @@ use crate::saveable::Saveable;
 /// For sharing across multiple tasks, obtain a lightweight [`HtmlSaverSender`]
 /// via [`sender`](Self::sender).
 pub struct HtmlSaverHandle<R: Saveable> {
-    sender: mpsc::Sender<R>,
+    sender: HtmlSaverSender<R>,
     shutdown: Option<oneshot::Sender<()>>,
     worker: Option<JoinHandle<()>>,
 }
 
 impl<R: Saveable> HtmlSaverHandle<R> {
     pub(crate) fn new(
-        sender: mpsc::Sender<R>,
+        sender: mpsc::Sender<Queued<R>>,
+        wal: Option<Arc<Wal>>,
+        overflow: Overflow<R>,
+        template: Option<KeyTemplate>,
         shutdown: oneshot::Sender<()>,
         worker: JoinHandle<()>,
     ) -> Self {
         Self {
-            sender,
+            sender: HtmlSaverSender {
+                sender,
+                wal,
+                overflow: Arc::new(overflow),
+                template: template.map(Arc::new),
+            },
             shutdown: Some(shutdown),
             worker: Some(worker),
         }
@@ -36,12 +57,40 @@ impl<R: Saveable> HtmlSaverHandle<R> {
     /// Queue an item for saving.
     ///
     /// This is a non-blocking operation that places the item into the internal
-    /// channel. Returns [`HtmlSaverError::ChannelClosed`] if the channel is
-    /// full or the worker has stopped.
-    pub fn save(&self, request: R) -> Result<()> {
-        self.sender
-            .try_send(request)
-            .map_err(|_| HtmlSaverError::ChannelClosed)
+    /// channel. If the channel is at capacity, the configured
+    /// [`OverflowPolicy`] decides what happens; with the default policy this
+    /// returns [`SaveError::Full`]. Returns [`SaveError::Closed`] if the worker
+    /// has stopped. Both errors hand the item back.
+    ///
+    /// With a [write-ahead log](crate::HtmlSaverBuilder::write_ahead_log)
+    /// configured, the item is appended to disk before this returns.
+    pub fn save(&self, request: R) -> Result<(), SaveError<R>> {
+        self.sender.save(request)
+    }
+
+    /// Queue an item for saving, waiting for channel capacity if it is full.
+    ///
+    /// Returns [`SaveError::Closed`] only if the worker has stopped.
+    pub async fn save_async(&self, request: R) -> Result<(), SaveError<R>> {
+        self.sender.save_async(request).await
+    }
+
+    /// Queue an item for saving, waiting at most `timeout` for channel capacity.
+    ///
+    /// Returns [`SaveError::Full`] if the channel is still full after
+    /// `timeout` and [`SaveError::Closed`] if the worker has stopped.
+    pub async fn save_timeout(&self, request: R, timeout: Duration) -> Result<(), SaveError<R>> {
+        self.sender.save_timeout(request, timeout).await
+    }
+
+    /// Queue an item for saving and return a [`SaveAck`] future that resolves
+    /// once the worker has processed it.
+    ///
+    /// The future yields the final storage key (including any prefix) after
+    /// the item has actually been written, or the upload error. Queuing itself
+    /// is non-blocking and fails like [`save`](Self::save).
+    pub fn save_with_ack(&self, request: R) -> Result<SaveAck, SaveError<R>> {
+        self.sender.save_with_ack(request)
     }
 
     /// Queue an item for saving, logging the error via `tracing` on failure
@@ -52,12 +101,15 @@ impl<R: Saveable> HtmlSaverHandle<R> {
         }
     }
 
+    /// Counters of items affected by the [`OverflowPolicy`] so far.
+    pub fn overflow_stats(&self) -> OverflowStats {
+        self.sender.overflow_stats()
+    }
+
     /// Create a lightweight, cloneable [`HtmlSaverSender`] that shares the
     /// same underlying channel.
     pub fn sender(&self) -> HtmlSaverSender<R> {
-        HtmlSaverSender {
-            sender: self.sender.clone(),
-        }
+        self.sender.clone()
     }
 
     /// Gracefully shut down the background worker.
@@ -80,23 +132,59 @@ impl<R: Saveable> HtmlSaverHandle<R> {
 /// signal or the worker join handle -- dropping all senders will not stop the
 /// worker.
 pub struct HtmlSaverSender<R: Saveable> {
-    sender: mpsc::Sender<R>,
+    sender: mpsc::Sender<Queued<R>>,
+    wal: Option<Arc<Wal>>,
+    overflow: Arc<Overflow<R>>,
+    template: Option<Arc<KeyTemplate>>,
 }
 
 impl<R: Saveable> Clone for HtmlSaverSender<R> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
+            wal: self.wal.clone(),
+            overflow: self.overflow.clone(),
+            template: self.template.clone(),
         }
     }
 }
 
 impl<R: Saveable> HtmlSaverSender<R> {
     /// Queue an item for saving.
-    pub fn save(&self, request: R) -> Result<()> {
+    pub fn save(&self, request: R) -> Result<(), SaveError<R>> {
+        let queued = self.journal(request)?;
+        self.try_enqueue(queued)
+    }
+
+    /// Queue an item for saving, waiting for channel capacity if it is full.
+    pub async fn save_async(&self, request: R) -> Result<(), SaveError<R>> {
+        let queued = self.journal(request)?;
         self.sender
-            .try_send(request)
-            .map_err(|_| HtmlSaverError::ChannelClosed)
+            .send(queued)
+            .await
+            .map_err(|e| SaveError::Closed(self.discard(e.0)))
+    }
+
+    /// Queue an item for saving, waiting at most `timeout` for channel capacity.
+    pub async fn save_timeout(&self, request: R, timeout: Duration) -> Result<(), SaveError<R>> {
+        let queued = self.journal(request)?;
+        self.sender
+            .send_timeout(queued, timeout)
+            .await
+            .map_err(|e| match e {
+                SendTimeoutError::Timeout(q) => SaveError::Full(self.discard(q)),
+                SendTimeoutError::Closed(q) => SaveError::Closed(self.discard(q)),
+            })
+    }
+
+    /// Queue an item for saving and return a [`SaveAck`] future that resolves
+    /// once the worker has processed it.
+    pub fn save_with_ack(&self, request: R) -> Result<SaveAck, SaveError<R>> {
+        let (tx, rx) = oneshot::channel();
+        let mut queued = self.journal(request)?;
+        queued.ack = Some(tx);
+        self.try_enqueue(queued)?;
+        Ok(SaveAck { rx })
     }
 
     /// Queue an item for saving, logging errors instead of returning them.
@@ -105,4 +193,175 @@ impl<R: Saveable> HtmlSaverSender<R> {
             tracing::error!("Failed to queue save request: {e}");
         }
     }
+
+    /// Counters of items affected by the [`OverflowPolicy`] so far.
+    pub fn overflow_stats(&self) -> OverflowStats {
+        self.overflow.counters.snapshot()
+    }
+
+    /// Render the item's key name and append the item to the write-ahead
+    /// log, if one is configured.
+    fn journal(&self, item: R) -> Result<Queued<R>, SaveError<R>> {
+        let name = match &self.template {
+            Some(template) => match template.render(&item, SystemTime::now()) {
+                Ok(name) => name,
+                Err(e) => return Err(SaveError::Key(item, e)),
+            },
+            None => item.name(),
+        };
+        let seq = match &self.wal {
+            Some(wal) => match wal.append(&name, &item.metadata(), item.content_bytes()) {
+                Ok(seq) => Some(seq),
+                Err(e) => return Err(SaveError::Wal(item, e)),
+            },
+            None => None,
+        };
+        Ok(Queued {
+            item,
+            name,
+            seq,
+            ack: None,
+        })
+    }
+
+    /// Place an already journaled item into the channel without waiting,
+    /// applying the [`OverflowPolicy`] if it is full.
+    fn try_enqueue(&self, queued: Queued<R>) -> Result<(), SaveError<R>> {
+        let queued = match self.sender.try_send(queued) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(q)) => return Err(SaveError::Closed(self.discard(q))),
+            Err(TrySendError::Full(q)) => q,
+        };
+
+        let counters = &self.overflow.counters;
+        match &self.overflow.policy {
+            OverflowPolicy::Reject => {
+                counters.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(SaveError::Full(self.discard(queued)))
+            }
+            OverflowPolicy::Block => self.enqueue_blocking(queued),
+            OverflowPolicy::DropOldest => {
+                let mut queued = queued;
+                loop {
+                    let Some(oldest) = self.overflow.pop_oldest() else {
+                        counters.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(SaveError::Full(self.discard(queued)));
+                    };
+                    counters.dropped_oldest.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!("Channel full, dropped oldest queued item");
+                    self.drop_item(oldest);
+
+                    match self.sender.try_send(queued) {
+                        Ok(()) => return Ok(()),
+                        Err(TrySendError::Closed(q)) => {
+                            return Err(SaveError::Closed(self.discard(q)));
+                        }
+                        Err(TrySendError::Full(q)) => queued = q,
+                    }
+                }
+            }
+            OverflowPolicy::DropNewest => {
+                counters.dropped_newest.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Channel full, dropped new item");
+                self.drop_item(queued);
+                Ok(())
+            }
+            OverflowPolicy::SpillToDisk(_) => {
+                let Some(spill) = &self.overflow.spill else {
+                    counters.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(SaveError::Full(self.discard(queued)));
+                };
+                let Queued {
+                    item,
+                    name,
+                    seq,
+                    ack,
+                } = queued;
+                match spill.write(&name, &item.metadata(), item.content_bytes(), ack) {
+                    Ok(()) => {
+                        counters.spilled.fetch_add(1, Ordering::Relaxed);
+                        // The spill file now carries the item durably.
+                        self.discard(Queued {
+                            item,
+                            name,
+                            seq,
+                            ack: None,
+                        });
+                        Ok(())
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to spill item to disk: {e}");
+                        counters.rejected.fetch_add(1, Ordering::Relaxed);
+                        Err(SaveError::Full(self.discard(Queued {
+                            item,
+                            name,
+                            seq,
+                            ack: None,
+                        })))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for channel capacity by blocking the current thread.
+    fn enqueue_blocking(&self, queued: Queued<R>) -> Result<(), SaveError<R>> {
+        let result = match Handle::try_current() {
+            Err(_) => self.sender.blocking_send(queued).map_err(|e| e.0),
+            Ok(rt) if rt.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| rt.block_on(self.sender.send(queued)))
+                    .map_err(|e| e.0)
+            }
+            Ok(_) => {
+                tracing::warn!("Cannot block on a current-thread runtime, rejecting item");
+                self.overflow
+                    .counters
+                    .rejected
+                    .fetch_add(1, Ordering::Relaxed);
+                return Err(SaveError::Full(self.discard(queued)));
+            }
+        };
+        result.map_err(|q| SaveError::Closed(self.discard(q)))
+    }
+
+    /// Discard an item on behalf of the overflow policy, resolving its
+    /// acknowledgement with [`HtmlSaverError::Dropped`].
+    fn drop_item(&self, mut queued: Queued<R>) -> R {
+        if let Some(ack) = queued.ack.take() {
+            let _ = ack.send(Err(HtmlSaverError::Dropped));
+        }
+        self.discard(queued)
+    }
+
+    /// Acknowledge a journaled item that was rejected by the channel so it is
+    /// not replayed on the next start, and hand the item back.
+    fn discard(&self, queued: Queued<R>) -> R {
+        if let (Some(wal), Some(seq)) = (&self.wal, queued.seq)
+            && let Err(e) = wal.ack(seq)
+        {
+            tracing::error!("Failed to acknowledge rejected item in WAL: {e}");
+        }
+        queued.item
+    }
+}
+
+/// Future returned by [`HtmlSaverHandle::save_with_ack`] and
+/// [`HtmlSaverSender::save_with_ack`].
+///
+/// Resolves to the storage key the item was written under, or to the error
+/// that made the upload fail. If the worker stops before processing the item,
+/// it resolves to [`HtmlSaverError::ChannelClosed`].
+#[must_use = "the acknowledgement is lost if the future is dropped"]
+pub struct SaveAck {
+    rx: oneshot::Receiver<crate::Result<String>>,
+}
+
+impl Future for SaveAck {
+    type Output = crate::Result<String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|r| r.unwrap_or(Err(HtmlSaverError::ChannelClosed)))
+    }
 }
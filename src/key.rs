@@ -0,0 +1,253 @@
+//! Validation of storage keys.
+//!
+//! Keys are built from [`Saveable::name`](crate::Saveable::name), which often
+//! comes from scraped URLs. Before an item is stored the worker checks its key
+//! according to the configured [`KeyPolicy`], and [`FsStorage`](crate::FsStorage)
+//! validates every key it is given, so a name like `../../etc/cron.d/x` can
+//! never escape the base directory.
+//!
+//! A valid key is a relative, `/`-separated path that:
+//!
+//! - is not empty and at most [`MAX_KEY_LEN`] bytes long (the S3 limit);
+//! - has no empty, `.` or `..` segments, and does not start or end with `/`;
+//! - has no segment longer than [`MAX_SEGMENT_LEN`] bytes (the usual file
+//!   name limit);
+//! - contains no NUL bytes, other control characters (which S3 cannot return
+//!   in XML listings) or backslashes.
+
+use std::path::{Component, Path};
+
+use crate::error::{HtmlSaverError, Result};
+
+/// Longest key accepted, in bytes.
+pub const MAX_KEY_LEN: usize = 1024;
+
+/// Longest segment (file or directory name) accepted, in bytes.
+pub const MAX_SEGMENT_LEN: usize = 255;
+
+/// What the worker does with an item whose name is not a valid key.
+///
+/// The policy applies to [`Saveable::name`](crate::Saveable::name), or to the
+/// rendered [key template](crate::template), before the builder's prefix is
+/// added, so normalizing `..` never leaves the prefix.
+///
+/// Set with [`HtmlSaverBuilder::key_policy`](crate::HtmlSaverBuilder::key_policy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Fail the item with [`HtmlSaverError::InvalidKey`].
+    #[default]
+    Reject,
+    /// Rewrite the key with [`normalize_key`]; items whose key cannot be
+    /// rewritten still fail.
+    Normalize,
+}
+
+impl KeyPolicy {
+    /// Apply the policy to `key`, returning the key to store under.
+    pub fn apply(&self, key: String) -> Result<String> {
+        match self {
+            Self::Reject => validate_key(&key).map(|()| key),
+            Self::Normalize => normalize_key(&key),
+        }
+    }
+}
+
+/// Check that `key` is a valid storage key.
+///
+/// Returns [`HtmlSaverError::InvalidKey`] describing the first problem found.
+///
+/// ```
+/// use html_saver::key::validate_key;
+///
+/// assert!(validate_key("2026/01/index.html").is_ok());
+/// assert!(validate_key("../../etc/cron.d/x").is_err());
+/// assert!(validate_key("/etc/passwd").is_err());
+/// ```
+pub fn validate_key(key: &str) -> Result<()> {
+    let invalid = |reason| {
+        Err(HtmlSaverError::InvalidKey {
+            key: key.to_string(),
+            reason,
+        })
+    };
+    if key.is_empty() {
+        return invalid("empty key");
+    }
+    if key.len() > MAX_KEY_LEN {
+        return invalid("longer than 1024 bytes");
+    }
+    if key.starts_with('/') || Path::new(key).has_root() {
+        return invalid("absolute path");
+    }
+    if key.contains('\0') {
+        return invalid("contains a NUL byte");
+    }
+    if key.chars().any(char::is_control) {
+        return invalid("contains a control character");
+    }
+    if key.contains('\\') {
+        return invalid("contains a backslash");
+    }
+    for segment in key.split('/') {
+        match segment {
+            "" => return invalid("empty path segment"),
+            "." | ".." => return invalid("`.` or `..` path segment"),
+            _ if segment.len() > MAX_SEGMENT_LEN => {
+                return invalid("path segment longer than 255 bytes");
+            }
+            _ if !is_plain(segment) => return invalid("path segment is a drive or prefix"),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite `key` into a valid storage key.
+///
+/// Backslashes become `/`, control characters become `_`, empty and `.`
+/// segments are dropped, `..` removes the previous segment (but never climbs
+/// above the root) and overlong segments are truncated. Fails with
+/// [`HtmlSaverError::InvalidKey`] if nothing is left or the result is still
+/// too long.
+///
+/// ```
+/// use html_saver::key::normalize_key;
+///
+/// assert_eq!(normalize_key("/a//b/./../c.html").unwrap(), "a/c.html");
+/// assert_eq!(normalize_key("../../etc/cron.d/x").unwrap(), "etc/cron.d/x");
+/// ```
+pub fn normalize_key(key: &str) -> Result<String> {
+    let cleaned: String = key
+        .chars()
+        .map(|c| match c {
+            '\\' => '/',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let mut segments: Vec<String> = Vec::new();
+    for segment in cleaned.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => {
+                let mut segment = truncate(segment, MAX_SEGMENT_LEN).to_string();
+                if !is_plain(&segment) {
+                    segment = segment.replace(':', "_");
+                }
+                segments.push(segment);
+            }
+        }
+    }
+
+    let normalized = segments.join("/");
+    validate_key(&normalized).map_err(|e| match e {
+        HtmlSaverError::InvalidKey { reason, .. } => HtmlSaverError::InvalidKey {
+            key: key.to_string(),
+            reason,
+        },
+        e => e,
+    })?;
+    Ok(normalized)
+}
+
+/// Whether `segment` is an ordinary file name on this platform, rather than
+/// e.g. a Windows drive such as `C:`.
+fn is_plain(segment: &str) -> bool {
+    let mut components = Path::new(segment).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// The longest prefix of `s` of at most `max` bytes that ends on a character
+/// boundary.
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(key: &str) -> &'static str {
+        match validate_key(key) {
+            Err(HtmlSaverError::InvalidKey { reason, .. }) => reason,
+            other => panic!("expected InvalidKey for {key:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn valid_keys_pass() {
+        for key in [
+            "index.html",
+            "2026/01/15/12-30-00_200_abc.html",
+            "clients/42/page with spaces.html",
+            "ünïcödé/ページ.html",
+            "a..b/c...html",
+        ] {
+            assert!(validate_key(key).is_ok(), "{key}");
+        }
+    }
+
+    #[test]
+    fn traversal_and_absolute_paths_are_rejected() {
+        assert_eq!(reason("../../etc/cron.d/x"), "`.` or `..` path segment");
+        assert_eq!(reason("a/../../b"), "`.` or `..` path segment");
+        assert_eq!(reason("a/./b"), "`.` or `..` path segment");
+        assert_eq!(reason("/etc/passwd"), "absolute path");
+        assert_eq!(reason("a//b"), "empty path segment");
+        assert_eq!(reason("a/"), "empty path segment");
+        assert_eq!(reason("..\\..\\x"), "contains a backslash");
+    }
+
+    #[test]
+    fn invalid_characters_and_lengths_are_rejected() {
+        assert_eq!(reason(""), "empty key");
+        assert_eq!(reason("a\0b"), "contains a NUL byte");
+        assert_eq!(reason("a\nb"), "contains a control character");
+        assert_eq!(
+            reason(&format!("{}.html", "a".repeat(300))),
+            "path segment longer than 255 bytes"
+        );
+        assert_eq!(reason(&["a"; 600].join("/")), "longer than 1024 bytes");
+    }
+
+    #[test]
+    fn normalize_resolves_and_cleans() {
+        assert_eq!(normalize_key("/a//b/./../c.html").unwrap(), "a/c.html");
+        assert_eq!(normalize_key("../../etc/cron.d/x").unwrap(), "etc/cron.d/x");
+        assert_eq!(normalize_key("a\\b\\c.html").unwrap(), "a/b/c.html");
+        assert_eq!(normalize_key("a\0b\r\n.html").unwrap(), "a_b__.html");
+
+        let long = normalize_key(&format!("{}.html", "é".repeat(200))).unwrap();
+        assert_eq!(long.len(), 254);
+        assert!(validate_key(&long).is_ok());
+    }
+
+    #[test]
+    fn normalize_fails_when_nothing_is_left() {
+        assert!(matches!(
+            normalize_key("../.."),
+            Err(HtmlSaverError::InvalidKey { key, reason: "empty key" }) if key == "../.."
+        ));
+    }
+
+    #[test]
+    fn policies() {
+        assert!(KeyPolicy::Reject.apply("../x".into()).is_err());
+        assert_eq!(KeyPolicy::Normalize.apply("../x".into()).unwrap(), "x");
+        assert_eq!(KeyPolicy::default(), KeyPolicy::Reject);
+    }
+}
@@ -7,10 +7,14 @@
 //!
 //! `html_saver` runs a background worker that collects [`Saveable`] items,
 //! optionally sanitizes their HTML content through a [`SanitizerPipeline`],
-//! and writes the results to a [`Storage`] backend (local filesystem, S3, or
-//! your own implementation).
+//! and writes the results to a [`Storage`] backend (local filesystem, S3, Google
+//! Cloud Storage, Azure Blob Storage, or your own implementation).
 //!
 //! Items are batched by count and/or time interval to reduce I/O overhead.
+//! Failed uploads can be retried with exponential backoff via a
+//! [`RetryPolicy`]; items that still fail can be diverted to a
+//! [dead-letter](dead_letter) storage and replayed later. An optional
+//! [write-ahead log](wal) keeps queued items on disk so they survive crashes.
 //!
 //! ## Quick start
 //!
@@ -42,27 +46,64 @@
 //! | Feature | Default | Description |
 //! |---------|---------|-------------|
 //! | `s3` | **yes** | Enables [`S3Storage`] and re-exports from `aws-sdk-s3` / `aws-config`. |
+//! | `gcs` | no | Enables [`GcsStorage`] for Google Cloud Storage. |
+//! | `azure` | no | Enables [`AzureBlobStorage`] for Azure Blob Storage. |
+//! | `object-store` | no | Enables [`ObjectStoreStorage`] over any [`object_store`] backend and re-exports the crate. |
 //! | `rustls-tls` | no | Use `rustls` instead of the platform TLS for the AWS SDK. |
+//! | `gzip` | no | gzip [`Compression`] of stored documents via `flate2`. |
+//! | `zstd` | no | Zstandard [`Compression`] of stored documents. |
+//! | `brotli` | no | Brotli [`Compression`] of stored documents. |
 
+pub mod compression;
 pub mod config;
+pub mod dead_letter;
+pub mod dedup;
 pub mod error;
 pub mod handle;
+pub mod key;
+pub mod overflow;
+pub mod overwrite;
+pub mod retry;
 pub mod sanitizer;
 pub mod saveable;
 pub mod storage;
+pub mod template;
+pub mod wal;
 mod worker;
 
+#[cfg(feature = "object-store")]
+pub use object_store;
+
+pub use compression::Compression;
 pub use config::HtmlSaverBuilder;
-pub use error::{HtmlSaverError, Result};
-pub use handle::{HtmlSaverHandle, HtmlSaverSender};
+pub use dedup::DedupConfig;
+pub use error::{HtmlSaverError, Result, SaveError};
+pub use handle::{HtmlSaverHandle, HtmlSaverSender, SaveAck};
+pub use key::KeyPolicy;
+pub use overflow::{OverflowPolicy, OverflowStats};
+pub use overwrite::{KeySuffix, OverwritePolicy};
+pub use retry::RetryPolicy;
 pub use sanitizer::{
-    RegexSanitizer, Sanitizer, SanitizerPipeline, SelectorAction, SelectorSanitizer,
-    SubstringSanitizer,
+    RegexSanitizer, Sanitizer, SanitizerExecutor, SanitizerPipeline, SelectorAction,
+    SelectorSanitizer, SubstringSanitizer,
 };
 pub use saveable::Saveable;
+#[cfg(feature = "azure")]
+pub use storage::AzureBlobStorage;
+#[cfg(feature = "gcs")]
+pub use storage::GcsStorage;
+#[cfg(feature = "object-store")]
+pub use storage::ObjectStoreStorage;
 #[cfg(feature = "s3")]
-pub use storage::{Credentials, Region, S3Client, S3Config, S3ConfigBuilder, S3Storage};
-pub use storage::{FsStorage, Storage};
+pub use storage::{
+    ChecksumAlgorithm, Credentials, ObjectCannedAcl, Region, S3Client, S3Config, S3ConfigBuilder,
+    S3Storage, S3UploadOptions, ServerSideEncryption, StorageClass,
+};
+pub use storage::{
+    FaultyStorage, FsStorage, MemoryStorage, ObjectInfo, ObjectMetadata, Storage, StoredObject,
+};
+pub use template::KeyTemplate;
+pub use wal::WalConfig;
 
 use std::any::Any;
 use std::sync::OnceLock;
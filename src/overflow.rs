@@ -0,0 +1,427 @@
+//! Policies for what happens when the channel to the worker is full.
+//!
+//! The policy is configured with
+//! [`HtmlSaverBuilder::overflow_policy`](crate::HtmlSaverBuilder::overflow_policy)
+//! and applies to the non-blocking save methods of both
+//! [`HtmlSaverHandle`](crate::HtmlSaverHandle) and
+//! [`HtmlSaverSender`](crate::HtmlSaverSender). Every item that does not make
+//! it into the channel is counted in [`OverflowStats`].
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::Result;
+use crate::retry::RetryPolicy;
+use crate::storage::ObjectMetadata;
+use crate::wal::{self, Record};
+use crate::worker::{Queued, SharedReceiver};
+
+const SPILL_EXT: &str = "spill";
+const TMP_EXT: &str = "spill.tmp";
+const CORRUPT_EXT: &str = "spill.corrupt";
+
+/// What to do with an item when the channel to the worker is full.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Return [`SaveError::Full`](crate::SaveError::Full) with the item.
+    #[default]
+    Reject,
+    /// Block the calling thread until there is room in the channel.
+    ///
+    /// Blocking requires a multi-threaded Tokio runtime (or a caller outside
+    /// of any runtime): on a current-thread runtime, the default of
+    /// `#[tokio::main(flavor = "current_thread")]` and `#[tokio::test]`, the
+    /// worker could never make room. Building a saver with this policy on a
+    /// current-thread runtime therefore fails with
+    /// [`HtmlSaverError::Config`](crate::HtmlSaverError::Config), and items
+    /// saved from within one are rejected as with [`Reject`](Self::Reject).
+    Block,
+    /// Discard the oldest queued item to make room for the new one.
+    DropOldest,
+    /// Silently discard the new item; `save` still returns `Ok`.
+    DropNewest,
+    /// Write the item to files in the given directory. The worker picks them
+    /// up on its next flush tick, including after a restart. A file is kept
+    /// until its item has been uploaded or dead-lettered; failed uploads are
+    /// retried after the [`RetryPolicy`](crate::RetryPolicy) backoff, and
+    /// unreadable files are renamed to `<id>.spill.corrupt`.
+    SpillToDisk(PathBuf),
+}
+
+/// Snapshot of the overflow counters of a saver.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OverflowStats {
+    /// Items rejected back to the caller.
+    pub rejected: u64,
+    /// Queued items discarded to make room for newer ones.
+    pub dropped_oldest: u64,
+    /// New items discarded because the channel was full.
+    pub dropped_newest: u64,
+    /// Items written to the spill directory.
+    pub spilled: u64,
+}
+
+impl OverflowStats {
+    /// Total number of items that did not go straight into the channel.
+    pub fn total(&self) -> u64 {
+        self.rejected + self.dropped_oldest + self.dropped_newest + self.spilled
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OverflowCounters {
+    pub rejected: AtomicU64,
+    pub dropped_oldest: AtomicU64,
+    pub dropped_newest: AtomicU64,
+    pub spilled: AtomicU64,
+}
+
+impl OverflowCounters {
+    pub fn snapshot(&self) -> OverflowStats {
+        OverflowStats {
+            rejected: self.rejected.load(Ordering::Relaxed),
+            dropped_oldest: self.dropped_oldest.load(Ordering::Relaxed),
+            dropped_newest: self.dropped_newest.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Overflow handling shared by all senders of one saver.
+pub(crate) struct Overflow<R> {
+    pub policy: OverflowPolicy,
+    pub counters: OverflowCounters,
+    pub spill: Option<Arc<Spill>>,
+    /// Used by [`OverflowPolicy::DropOldest`] to evict from the channel
+    /// without keeping it open after the worker is gone.
+    receiver: Weak<Mutex<mpsc::Receiver<Queued<R>>>>,
+}
+
+impl<R> Overflow<R> {
+    pub fn new(policy: OverflowPolicy, spill: Option<Arc<Spill>>, rx: &SharedReceiver<R>) -> Self {
+        Self {
+            policy,
+            counters: OverflowCounters::default(),
+            spill,
+            receiver: rx.downgrade(),
+        }
+    }
+
+    /// Take the oldest item out of the channel.
+    pub fn pop_oldest(&self) -> Option<Queued<R>> {
+        let rx = self.receiver.upgrade()?;
+        let mut rx = rx.lock().unwrap_or_else(|e| e.into_inner());
+        rx.try_recv().ok()
+    }
+}
+
+/// Directory of spilled items, one file per item, drained by the worker.
+pub(crate) struct Spill {
+    dir: PathBuf,
+    retry: RetryPolicy,
+    next_id: AtomicU64,
+    acks: Mutex<HashMap<u64, oneshot::Sender<Result<String>>>>,
+    /// Items handed to the worker and not yet completed, so a later drain
+    /// does not pick them up a second time.
+    claimed: Mutex<HashSet<u64>>,
+    /// Failed attempts of items waiting to be retried, and when the next one
+    /// is due.
+    backoff: Mutex<HashMap<u64, (u32, Instant)>>,
+}
+
+impl Spill {
+    /// Open the spill directory, removing temp files of writes that were
+    /// interrupted by a crash.
+    pub fn open(dir: &Path, retry: RetryPolicy) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.to_str().is_some_and(|p| p.ends_with(TMP_EXT)) {
+                tracing::warn!("Removing incomplete spill file {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+        let next_id = list(dir)?.last().map_or(0, |(id, _)| id + 1);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            retry,
+            next_id: AtomicU64::new(next_id),
+            acks: Mutex::new(HashMap::new()),
+            claimed: Mutex::new(HashSet::new()),
+            backoff: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Persist an item. The file is written and synced under a temporary
+    /// name, then renamed so the worker never sees a partial item, and the
+    /// rename is synced too: once this returns, the item survives a crash.
+    pub fn write(
+        &self,
+        name: &str,
+        metadata: &ObjectMetadata,
+        content: &[u8],
+        ack: Option<oneshot::Sender<Result<String>>>,
+    ) -> io::Result<()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let buf = wal::encode_record(id, name, metadata, content)?;
+        // Register the acknowledgement first: the worker may pick the file up
+        // as soon as it is renamed into place.
+        if let Some(ack) = ack {
+            self.lock_acks().insert(id, ack);
+        }
+        let path = self.path(id);
+        let tmp = path.with_extension(TMP_EXT);
+        let written = File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(&buf)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&tmp, &path))
+            .and_then(|()| sync_dir(&self.dir));
+        written.inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+            self.lock_acks().remove(&id);
+        })
+    }
+
+    /// All spilled items currently on disk, oldest first.
+    pub fn pending(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        list(&self.dir)
+    }
+
+    /// Spilled items not already being processed and not waiting for a
+    /// retry, oldest first. They are claimed until [`complete`](Self::complete)
+    /// or [`retry_later`](Self::retry_later) is called.
+    pub fn claim_pending(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut claimed = self.claimed.lock().unwrap_or_else(|e| e.into_inner());
+        let backoff = self.lock_backoff();
+        let now = Instant::now();
+        let mut pending = self.pending()?;
+        pending.retain(|(id, _)| {
+            let due = backoff.get(id).is_none_or(|&(_, at)| at <= now);
+            due && claimed.insert(*id)
+        });
+        Ok(pending)
+    }
+
+    /// Give up a claim so the item is picked up by the next drain.
+    fn release(&self, id: u64) {
+        self.claimed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<Record> {
+        let data = fs::read(path)?;
+        wal::decode_record(&data)
+            .map(|(record, _)| record)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "corrupt spill file"))
+    }
+
+    /// Remove an item that was uploaded or dead-lettered and hand back its
+    /// acknowledgement channel.
+    pub fn complete(&self, id: u64) -> Option<oneshot::Sender<Result<String>>> {
+        if let Err(e) = fs::remove_file(self.path(id)) {
+            tracing::error!("Failed to remove spilled item {id}: {e}");
+        }
+        self.lock_backoff().remove(&id);
+        self.release(id);
+        self.lock_acks().remove(&id)
+    }
+
+    /// Keep an item that failed on disk, to be picked up again once the
+    /// [`RetryPolicy`] backoff has passed, and hand back its acknowledgement
+    /// channel.
+    pub fn retry_later(&self, id: u64) -> Option<oneshot::Sender<Result<String>>> {
+        {
+            let mut backoff = self.lock_backoff();
+            let (failures, due) = backoff.entry(id).or_insert((0, Instant::now()));
+            *failures += 1;
+            *due = Instant::now() + self.retry.delay(*failures);
+        }
+        self.release(id);
+        self.lock_acks().remove(&id)
+    }
+
+    /// Move an item that cannot be decoded aside as `<id>.spill.corrupt` and
+    /// hand back its acknowledgement channel.
+    pub fn quarantine(&self, id: u64) -> Option<oneshot::Sender<Result<String>>> {
+        let path = self.path(id);
+        let corrupt = path.with_extension(CORRUPT_EXT);
+        tracing::error!(
+            "Spilled item {} is corrupt; moved it to {}",
+            path.display(),
+            corrupt.display()
+        );
+        if let Err(e) = fs::rename(&path, &corrupt) {
+            tracing::error!("Failed to move corrupt spilled item {id}: {e}");
+        }
+        self.lock_backoff().remove(&id);
+        self.release(id);
+        self.lock_acks().remove(&id)
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{id:020}.{SPILL_EXT}"))
+    }
+
+    fn lock_backoff(&self) -> std::sync::MutexGuard<'_, HashMap<u64, (u32, Instant)>> {
+        self.backoff.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_acks(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<u64, oneshot::Sender<Result<String>>>> {
+        self.acks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Make a rename in `dir` durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn list(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut items = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(SPILL_EXT)
+            && let Some(id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+        {
+            items.push((id, path));
+        }
+    }
+    items.sort_unstable();
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    #[test]
+    fn spill_round_trip_in_order() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let spill = Spill::open(tmp.path(), RetryPolicy::new()).unwrap();
+        spill.write("a.html", &html(), b"<p>a</p>", None).unwrap();
+        spill.write("b.html", &html(), b"<p>b</p>", None).unwrap();
+
+        let pending = spill.pending().unwrap();
+        let names: Vec<_> = pending
+            .iter()
+            .map(|(_, p)| spill.read(p).unwrap().name)
+            .collect();
+        assert_eq!(names, vec!["a.html", "b.html"]);
+
+        spill.complete(pending[0].0);
+        assert_eq!(spill.pending().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn spill_ids_continue_after_reopen() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        Spill::open(tmp.path(), RetryPolicy::new())
+            .unwrap()
+            .write("a.html", &html(), b"", None)
+            .unwrap();
+
+        let spill = Spill::open(tmp.path(), RetryPolicy::new()).unwrap();
+        spill.write("b.html", &html(), b"", None).unwrap();
+        let ids: Vec<_> = spill
+            .pending()
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn claimed_items_are_not_handed_out_twice() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let spill = Spill::open(tmp.path(), RetryPolicy::new()).unwrap();
+        spill.write("a.html", &html(), b"", None).unwrap();
+
+        let claimed = spill.claim_pending().unwrap();
+        assert_eq!(claimed.len(), 1);
+        spill.write("b.html", &html(), b"", None).unwrap();
+        assert_eq!(spill.claim_pending().unwrap().len(), 1);
+
+        spill.release(claimed[0].0);
+        assert_eq!(spill.claim_pending().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn interrupted_writes_are_removed_on_open() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let stale = tmp.path().join(format!("{:020}.{TMP_EXT}", 0));
+        fs::write(&stale, b"partial").unwrap();
+
+        let spill = Spill::open(tmp.path(), RetryPolicy::new()).unwrap();
+        assert!(!stale.exists());
+        assert!(spill.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_items_wait_for_the_backoff() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let retry = RetryPolicy::new()
+            .base_delay(std::time::Duration::from_secs(60))
+            .jitter(0.0);
+        let spill = Spill::open(tmp.path(), retry).unwrap();
+        spill.write("a.html", &html(), b"", None).unwrap();
+
+        let claimed = spill.claim_pending().unwrap();
+        spill.retry_later(claimed[0].0);
+        assert!(spill.claim_pending().unwrap().is_empty());
+        assert_eq!(spill.pending().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn corrupt_items_are_moved_aside() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let spill = Spill::open(tmp.path(), RetryPolicy::new()).unwrap();
+        spill.write("a.html", &html(), b"", None).unwrap();
+        let (id, path) = spill.claim_pending().unwrap().remove(0);
+        fs::write(&path, b"garbage").unwrap();
+
+        let err = spill.read(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        spill.quarantine(id);
+        assert!(spill.pending().unwrap().is_empty());
+        assert!(path.with_extension(CORRUPT_EXT).exists());
+    }
+
+    #[test]
+    fn stats_total() {
+        let stats = OverflowStats {
+            rejected: 1,
+            dropped_oldest: 2,
+            dropped_newest: 3,
+            spilled: 4,
+        };
+        assert_eq!(stats.total(), 10);
+    }
+}
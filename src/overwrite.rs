@@ -0,0 +1,317 @@
+//! What happens when an item's key is already taken.
+//!
+//! Set with [`HtmlSaverBuilder::overwrite_policy`](crate::HtmlSaverBuilder::overwrite_policy).
+//! Every policy but [`OverwritePolicy::Overwrite`] writes with
+//! [`Storage::put_if_absent`], a conditional write on the built-in backends
+//! (`If-None-Match: *` on S3 and Azure, `ifGenerationMatch=0` on GCS, a
+//! create-new link on [`FsStorage`](crate::FsStorage)), so two items racing
+//! for one key never replace each other.
+//!
+//! The acknowledgement of [`save_with_ack`](crate::HtmlSaverHandle::save_with_ack)
+//! resolves to the key the item was finally stored under.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::key::validate_key;
+use crate::storage::{ObjectMetadata, Storage};
+
+/// Most suffixed keys tried before giving up on an item.
+const MAX_ATTEMPTS: u32 = 1000;
+
+/// What to do when an object already exists under an item's key.
+///
+/// Every policy but [`Overwrite`](Self::Overwrite) needs a backend that
+/// implements [`Storage::put_if_absent`] or [`Storage::exists`]; with a
+/// backend that only implements [`Storage::put`], items fail with
+/// [`HtmlSaverError::Unsupported`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing object.
+    #[default]
+    Overwrite,
+    /// Keep the existing object and drop the item; its acknowledgement
+    /// resolves to the existing key.
+    SkipIfExists,
+    /// Store the item under the first free key with the given suffix.
+    Suffix(KeySuffix),
+}
+
+/// Suffix added to a taken key by [`OverwritePolicy::Suffix`].
+///
+/// The suffix goes before the first extension of the file name, so
+/// `2026/page.html.gz` becomes e.g. `2026/page-1.html.gz`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySuffix {
+    /// `page-1.html`, `page-2.html`, ...: the first free one. Each taken key
+    /// costs one request.
+    Counter,
+    /// `page-1768561845123.html`: milliseconds since the Unix epoch, followed
+    /// by a counter if that key is taken too.
+    Timestamp,
+    /// `page-1a2b3c4d5e6f7a8b.html`: the first 16 hex digits of the SHA-256
+    /// of the stored content. If that key is taken as well, the item is
+    /// skipped when the object there holds the same content, and stored
+    /// under a counter (`page-1a2b3c4d5e6f7a8b-1.html`) when it does not.
+    /// Backends without [`Storage::get`] are trusted to hold the same content.
+    Hash,
+}
+
+impl OverwritePolicy {
+    /// Store `content` under `key` according to the policy, returning the key
+    /// it ends up under.
+    pub(crate) async fn put<S: Storage>(
+        &self,
+        storage: &S,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<String> {
+        let suffix = match self {
+            Self::Overwrite => {
+                storage.put(key, content, metadata).await?;
+                return Ok(key.to_string());
+            }
+            Self::SkipIfExists => {
+                if !storage.put_if_absent(key, content, metadata).await? {
+                    tracing::info!("Skipped {key}: already exists");
+                }
+                return Ok(key.to_string());
+            }
+            Self::Suffix(suffix) => suffix,
+        };
+
+        if storage.put_if_absent(key, content, metadata).await? {
+            return Ok(key.to_string());
+        }
+        let base = match suffix {
+            KeySuffix::Counter => key.to_string(),
+            KeySuffix::Timestamp => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                let candidate = with_suffix(key, &millis.to_string());
+                if try_put(storage, &candidate, content, metadata).await? {
+                    return Ok(candidate);
+                }
+                candidate
+            }
+            KeySuffix::Hash => {
+                let candidate = with_suffix(key, &content_hash(content));
+                if try_put(storage, &candidate, content, metadata).await? {
+                    return Ok(candidate);
+                }
+                if holds(storage, &candidate, content).await? {
+                    tracing::info!("Skipped {key}: same content exists as {candidate}");
+                    return Ok(candidate);
+                }
+                tracing::warn!("Hash suffix collision on {candidate}, adding a counter");
+                candidate
+            }
+        };
+
+        for n in 1..=MAX_ATTEMPTS {
+            let candidate = with_suffix(&base, &n.to_string());
+            if try_put(storage, &candidate, content, metadata).await? {
+                return Ok(candidate);
+            }
+        }
+        Err(HtmlSaverError::StorageUpload(
+            format!("no free key for {key} after {MAX_ATTEMPTS} attempts").into(),
+        ))
+    }
+}
+
+/// Write a suffixed key, which must still be valid.
+async fn try_put<S: Storage>(
+    storage: &S,
+    key: &str,
+    content: &[u8],
+    metadata: &ObjectMetadata,
+) -> Result<bool> {
+    validate_key(key)?;
+    storage.put_if_absent(key, content, metadata).await
+}
+
+/// Insert `-{suffix}` before the first extension of the last segment of
+/// `key`. A leading dot does not start an extension.
+fn with_suffix(key: &str, suffix: &str) -> String {
+    let name_start = key.rfind('/').map_or(0, |i| i + 1);
+    let name = &key[name_start..];
+    let at = match name.get(1..).and_then(|rest| rest.find('.')) {
+        Some(i) => name_start + 1 + i,
+        None => key.len(),
+    };
+    format!("{}-{suffix}{}", &key[..at], &key[at..])
+}
+
+/// Whether the object under `key` holds `content`. Backends that cannot
+/// read are assumed to.
+async fn holds<S: Storage>(storage: &S, key: &str, content: &[u8]) -> Result<bool> {
+    match storage.get(key).await {
+        Ok(object) => Ok(object.is_some_and(|object| object.content == content)),
+        Err(HtmlSaverError::Unsupported(_)) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// First 16 hex digits of the SHA-256 of `content`.
+fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::FsStorage;
+
+    use super::*;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    #[test]
+    fn suffixes_go_before_the_first_extension() {
+        assert_eq!(with_suffix("page.html", "1"), "page-1.html");
+        assert_eq!(with_suffix("2026/page.html.gz", "1"), "2026/page-1.html.gz");
+        assert_eq!(with_suffix("v1.2/page", "1"), "v1.2/page-1");
+        assert_eq!(with_suffix(".hidden", "1"), ".hidden-1");
+    }
+
+    #[tokio::test]
+    async fn counter_takes_the_first_free_key() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let policy = OverwritePolicy::Suffix(KeySuffix::Counter);
+
+        let mut keys = Vec::new();
+        for content in [&b"a"[..], b"b", b"c"] {
+            keys.push(
+                policy
+                    .put(&storage, "page.html", content, &html())
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(keys, ["page.html", "page-1.html", "page-2.html"]);
+        assert_eq!(std::fs::read(tmp.path().join("page-2.html")).unwrap(), b"c");
+    }
+
+    #[tokio::test]
+    async fn hash_suffix_deduplicates_identical_content() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let policy = OverwritePolicy::Suffix(KeySuffix::Hash);
+
+        let first = policy
+            .put(&storage, "page.html", b"a", &html())
+            .await
+            .unwrap();
+        let second = policy
+            .put(&storage, "page.html", b"b", &html())
+            .await
+            .unwrap();
+        let third = policy
+            .put(&storage, "page.html", b"b", &html())
+            .await
+            .unwrap();
+
+        assert_eq!(first, "page.html");
+        assert_eq!(second, format!("page-{}.html", content_hash(b"b")));
+        assert_eq!(third, second);
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn hash_suffix_collisions_fall_back_to_a_counter() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let policy = OverwritePolicy::Suffix(KeySuffix::Hash);
+        let hashed = format!("page-{}.html", content_hash(b"b"));
+
+        policy
+            .put(&storage, "page.html", b"a", &html())
+            .await
+            .unwrap();
+        // Different content that happens to be stored under the same hash.
+        std::fs::write(tmp.path().join(&hashed), b"collision").unwrap();
+
+        let key = policy
+            .put(&storage, "page.html", b"b", &html())
+            .await
+            .unwrap();
+        assert_eq!(key, format!("page-{}-1.html", content_hash(b"b")));
+        assert_eq!(std::fs::read(tmp.path().join(&key)).unwrap(), b"b");
+    }
+
+    #[tokio::test]
+    async fn timestamp_suffix_is_added_to_taken_keys() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let policy = OverwritePolicy::Suffix(KeySuffix::Timestamp);
+
+        let first = policy
+            .put(&storage, "page.html", b"a", &html())
+            .await
+            .unwrap();
+        let second = policy
+            .put(&storage, "page.html", b"b", &html())
+            .await
+            .unwrap();
+        assert_eq!(first, "page.html");
+        assert!(second.starts_with("page-") && second.ends_with(".html"));
+        assert_ne!(second, first);
+    }
+
+    /// Backend that implements nothing but `put`.
+    struct PutOnly;
+
+    impl Storage for PutOnly {
+        async fn put(&self, _key: &str, _content: &[u8], _metadata: &ObjectMetadata) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn conditional_policies_need_more_than_put() {
+        for policy in [
+            OverwritePolicy::SkipIfExists,
+            OverwritePolicy::Suffix(KeySuffix::Counter),
+        ] {
+            let result = policy.put(&PutOnly, "page.html", b"a", &html()).await;
+            assert!(matches!(
+                result,
+                Err(HtmlSaverError::Unsupported("put_if_absent"))
+            ));
+        }
+        let key = OverwritePolicy::Overwrite
+            .put(&PutOnly, "page.html", b"a", &html())
+            .await
+            .unwrap();
+        assert_eq!(key, "page.html");
+    }
+
+    #[tokio::test]
+    async fn skip_keeps_the_existing_object() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path());
+        let policy = OverwritePolicy::SkipIfExists;
+
+        policy
+            .put(&storage, "page.html", b"a", &html())
+            .await
+            .unwrap();
+        let key = policy
+            .put(&storage, "page.html", b"b", &html())
+            .await
+            .unwrap();
+        assert_eq!(key, "page.html");
+        assert_eq!(std::fs::read(tmp.path().join("page.html")).unwrap(), b"a");
+    }
+}
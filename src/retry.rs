@@ -0,0 +1,210 @@
+//! Retry policy with exponential backoff for failed storage uploads.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::HtmlSaverError;
+
+type RetryPredicate = Arc<dyn Fn(&HtmlSaverError) -> bool + Send + Sync>;
+
+/// Controls how the background worker retries a failed [`Storage::put`](crate::Storage::put).
+///
+/// The delay before retry `n` (starting at 1) is `base_delay * 2^(n - 1)`,
+/// capped at `max_delay`. With a non-zero [`jitter`](Self::jitter) factor the
+/// delay is scaled by a random value in `[1 - jitter, 1]` so that many
+/// failing items do not retry in lockstep.
+///
+/// By default only [`HtmlSaverError::StorageUpload`] errors are retried; use
+/// [`retry_if`](Self::retry_if) to customize this.
+///
+/// # Example
+///
+/// ```
+/// use html_saver::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .base_delay(Duration::from_millis(200))
+///     .max_delay(Duration::from_secs(10))
+///     .jitter(0.5);
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retryable: RetryPredicate,
+}
+
+impl RetryPolicy {
+    /// Create a policy with sensible defaults.
+    ///
+    /// Defaults: 3 attempts, base delay 100 ms, max delay 10 s, jitter 0.5,
+    /// only [`HtmlSaverError::StorageUpload`] is retryable.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            retryable: Arc::new(|e| matches!(e, HtmlSaverError::StorageUpload(_))),
+        }
+    }
+
+    /// A policy that makes a single attempt and never retries.
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one. Values below 1
+    /// are treated as 1.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry; doubled for every subsequent retry.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound for the delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Fraction of the delay (`0.0..=1.0`) that is randomized. `0.0`
+    /// disables jitter; NaN and infinite values are treated as `0.0`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_finite() {
+            jitter.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// Decide which errors are worth retrying.
+    pub fn retry_if(
+        mut self,
+        predicate: impl Fn(&HtmlSaverError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retryable = Arc::new(predicate);
+        self
+    }
+
+    /// Returns `true` if another attempt should be made after `attempt`
+    /// attempts failed with `error`.
+    pub(crate) fn should_retry(&self, error: &HtmlSaverError, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.is_retryable(error)
+    }
+
+    /// Whether `error` is worth retrying at all, regardless of attempts.
+    pub(crate) fn is_retryable(&self, error: &HtmlSaverError) -> bool {
+        (self.retryable)(error)
+    }
+
+    /// Delay to wait before retry number `retry` (starting at 1).
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let exp = retry.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1u32 << exp)
+            .min(self.max_delay);
+
+        if self.jitter == 0.0 {
+            delay
+        } else {
+            delay.mul_f64(1.0 - self.jitter * fastrand::f64())
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_until_capped() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500))
+            .jitter(0.0);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(60), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .jitter(0.5);
+        for _ in 0..100 {
+            let d = policy.delay(1);
+            assert!(d >= Duration::from_millis(50) && d <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn non_finite_jitter_is_disabled() {
+        for jitter in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let policy = RetryPolicy::new()
+                .base_delay(Duration::from_millis(100))
+                .jitter(jitter);
+            assert_eq!(policy.delay(1), Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let policy = RetryPolicy::new().max_attempts(3);
+        let err = HtmlSaverError::StorageUpload("boom".into());
+        assert!(policy.should_retry(&err, 1));
+        assert!(policy.should_retry(&err, 2));
+        assert!(!policy.should_retry(&err, 3));
+    }
+
+    #[test]
+    fn none_never_retries() {
+        let err = HtmlSaverError::StorageUpload("boom".into());
+        assert!(!RetryPolicy::none().should_retry(&err, 1));
+    }
+
+    #[test]
+    fn only_storage_errors_retried_by_default() {
+        let policy = RetryPolicy::new();
+        assert!(!policy.should_retry(&HtmlSaverError::Config("bad".into()), 1));
+    }
+
+    #[test]
+    fn custom_predicate() {
+        let policy = RetryPolicy::new().retry_if(|e| matches!(e, HtmlSaverError::Config(_)));
+        assert!(policy.should_retry(&HtmlSaverError::Config("bad".into()), 1));
+        assert!(!policy.should_retry(&HtmlSaverError::StorageUpload("boom".into()), 1));
+    }
+}
@@ -0,0 +1,151 @@
+//! Where the worker runs the sanitizer pipeline.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use tokio::sync::oneshot;
+
+use crate::error::{HtmlSaverError, Result};
+
+/// Where the background worker runs the [`SanitizerPipeline`](super::SanitizerPipeline).
+///
+/// Sanitizers are synchronous and can be CPU-heavy -- a
+/// [`SelectorSanitizer`](super::SelectorSanitizer) re-parses the whole
+/// document once per rule. Running them off the async runtime keeps other
+/// tasks responsive. How many items are sanitized at once is set with
+/// [`HtmlSaverBuilder::sanitizer_parallelism`](crate::HtmlSaverBuilder::sanitizer_parallelism).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SanitizerExecutor {
+    /// Run on the worker task itself, one item at a time. Only suitable for
+    /// cheap sanitizers.
+    Inline,
+    /// Run on Tokio's blocking thread pool via
+    /// [`spawn_blocking`](tokio::task::spawn_blocking).
+    #[default]
+    Blocking,
+    /// Run on a dedicated pool of OS threads owned by the worker, one thread
+    /// per unit of parallelism.
+    Dedicated,
+}
+
+/// A job run on a [`ThreadPool`].
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs closures according to a [`SanitizerExecutor`].
+pub(crate) enum Runner {
+    Inline,
+    Blocking,
+    Dedicated(ThreadPool),
+}
+
+impl Runner {
+    /// Set up the executor; [`SanitizerExecutor::Dedicated`] starts `threads`
+    /// threads.
+    pub fn new(executor: SanitizerExecutor, threads: usize) -> Result<Self> {
+        Ok(match executor {
+            SanitizerExecutor::Inline => Self::Inline,
+            SanitizerExecutor::Blocking => Self::Blocking,
+            SanitizerExecutor::Dedicated => Self::Dedicated(
+                ThreadPool::new(threads)
+                    .map_err(|e| HtmlSaverError::Config(format!("sanitizer threads: {e}")))?,
+            ),
+        })
+    }
+
+    /// Run `f`, returning [`HtmlSaverError::Sanitizer`] if it panicked.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T> {
+        match self {
+            Self::Inline => panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| panicked()),
+            Self::Blocking => tokio::task::spawn_blocking(f)
+                .await
+                .map_err(|e| HtmlSaverError::Sanitizer(e.to_string())),
+            Self::Dedicated(pool) => {
+                let (tx, rx) = oneshot::channel();
+                pool.execute(Box::new(move || {
+                    let _ = tx.send(f());
+                }));
+                rx.await.map_err(|_| panicked())
+            }
+        }
+    }
+}
+
+fn panicked() -> HtmlSaverError {
+    HtmlSaverError::Sanitizer("sanitizer panicked".to_string())
+}
+
+/// Fixed-size pool of named threads sharing one task queue.
+///
+/// Threads exit once the pool is dropped and the queue has been drained.
+pub(crate) struct ThreadPool {
+    tx: mpsc::Sender<Task>,
+}
+
+impl ThreadPool {
+    fn new(threads: usize) -> std::io::Result<Self> {
+        let (tx, rx) = mpsc::channel::<Task>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..threads.max(1) {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("html-saver-sanitize-{i}"))
+                .spawn(move || {
+                    loop {
+                        // Release the lock before running the task so other
+                        // threads can pick up work.
+                        let task = rx.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                        match task {
+                            // A panicking task drops its result sender, which
+                            // the caller reports; the thread keeps serving.
+                            Ok(task) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+                            }
+                            Err(_) => return,
+                        }
+                    }
+                })?;
+        }
+        Ok(Self { tx })
+    }
+
+    fn execute(&self, task: Task) {
+        // The threads only stop once `tx` is dropped, so this cannot fail.
+        let _ = self.tx.send(task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dedicated_pool_runs_tasks_off_the_caller_thread() {
+        let runner = Runner::new(SanitizerExecutor::Dedicated, 2).unwrap();
+        let name = runner
+            .run(|| thread::current().name().map(str::to_string))
+            .await
+            .unwrap();
+        assert!(name.unwrap().starts_with("html-saver-sanitize-"));
+    }
+
+    #[tokio::test]
+    async fn dedicated_pool_survives_a_panicking_task() {
+        let runner = Runner::new(SanitizerExecutor::Dedicated, 1).unwrap();
+        let result = runner.run(|| -> u32 { panic!("boom") }).await;
+        assert!(matches!(result, Err(HtmlSaverError::Sanitizer(_))));
+        assert_eq!(runner.run(|| 42).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn inline_and_blocking_report_panics() {
+        for executor in [SanitizerExecutor::Inline, SanitizerExecutor::Blocking] {
+            let runner = Runner::new(executor, 1).unwrap();
+            let result = runner.run(|| -> u32 { panic!("boom") }).await;
+            assert!(matches!(result, Err(HtmlSaverError::Sanitizer(_))));
+        }
+    }
+}
@@ -1,7 +1,8 @@
 //! HTML sanitizer pipeline for redacting or transforming content before saving.
 //!
 //! Sanitizers implement the [`Sanitizer`] trait and are composed into a
-//! [`SanitizerPipeline`] that runs them sequentially.
+//! [`SanitizerPipeline`] that runs them sequentially. The background worker
+//! runs the pipeline on a [`SanitizerExecutor`].
 //!
 //! Built-in sanitizers:
 //!
@@ -9,11 +10,13 @@
 //! - [`RegexSanitizer`] -- regex-based replacements.
 //! - [`SelectorSanitizer`] -- CSS-selector-based element manipulation.
 
+pub(crate) mod executor;
 mod regex;
 mod selector;
 mod substring;
 
 pub use self::regex::RegexSanitizer;
+pub use executor::SanitizerExecutor;
 pub use selector::{SelectorAction, SelectorSanitizer};
 pub use substring::SubstringSanitizer;
 
@@ -25,6 +28,38 @@ pub use substring::SubstringSanitizer;
 pub trait Sanitizer: Send + Sync {
     /// Transform the given HTML content, returning the sanitized result.
     fn sanitize(&self, html: &str) -> String;
+
+    /// Returns `true` if this sanitizer should run on content of the given
+    /// MIME type.
+    ///
+    /// Defaults to all text types (`text/*`, JSON, XML and JavaScript).
+    /// Binary content is never passed to sanitizers.
+    fn applies_to(&self, content_type: &str) -> bool {
+        is_text(content_type)
+    }
+}
+
+/// MIME type without parameters, lowercased (`"Text/HTML; charset=utf-8"`
+/// becomes `"text/html"`).
+pub(crate) fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Returns `true` for MIME types whose content is text.
+pub(crate) fn is_text(content_type: &str) -> bool {
+    let essence = essence(content_type);
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json" | "application/xml" | "application/javascript"
+        )
 }
 
 /// An ordered chain of [`Sanitizer`] implementations applied sequentially.
@@ -55,6 +90,20 @@ impl SanitizerPipeline {
             .fold(html.to_string(), |acc, s| s.sanitize(&acc))
     }
 
+    /// Run only the sanitizers that [apply](Sanitizer::applies_to) to
+    /// `content_type`, in order.
+    pub fn sanitize_as(&self, content_type: &str, text: &str) -> String {
+        self.sanitizers
+            .iter()
+            .filter(|s| s.applies_to(content_type))
+            .fold(text.to_string(), |acc, s| s.sanitize(&acc))
+    }
+
+    /// Returns `true` if any sanitizer applies to `content_type`.
+    pub fn applies_to(&self, content_type: &str) -> bool {
+        self.sanitizers.iter().any(|s| s.applies_to(content_type))
+    }
+
     /// Returns `true` if no sanitizers have been added.
     pub fn is_empty(&self) -> bool {
         self.sanitizers.is_empty()
@@ -133,6 +182,37 @@ mod tests {
         assert_eq!(pipeline.sanitize(html), html);
     }
 
+    #[test]
+    fn text_types() {
+        assert!(is_text("text/html; charset=utf-8"));
+        assert!(is_text("application/json"));
+        assert!(is_text("application/ld+json"));
+        assert!(is_text("application/xml"));
+        assert!(is_text("application/rss+xml"));
+        assert!(!is_text("application/pdf"));
+        assert!(!is_text("image/png"));
+    }
+
+    #[test]
+    fn pipeline_skips_sanitizers_for_other_types() {
+        let mut pipeline = SanitizerPipeline::new();
+        pipeline.add(SelectorSanitizer::new(vec![(
+            "script",
+            SelectorAction::RemoveElement,
+        )]));
+        pipeline.add(SubstringSanitizer::new(vec![("secret", "***")]));
+
+        assert!(pipeline.applies_to("application/json"));
+        assert!(!pipeline.applies_to("application/pdf"));
+        assert_eq!(
+            pipeline.sanitize_as(
+                "application/json",
+                r#"{"token":"secret","html":"<script>"}"#
+            ),
+            r#"{"token":"***","html":"<script>"}"#
+        );
+    }
+
     #[test]
     fn pipeline_realistic_scraping_cleanup() {
         let mut pipeline = SanitizerPipeline::new();
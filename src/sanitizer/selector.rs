@@ -2,7 +2,7 @@
 
 use scraper::{Html, Selector, node::Node};
 
-use super::Sanitizer;
+use super::{Sanitizer, essence};
 
 /// Action to perform on HTML elements matching a CSS selector.
 #[derive(Clone, Debug)]
@@ -140,6 +140,14 @@ fn serialize_node(
 }
 
 impl Sanitizer for SelectorSanitizer {
+    /// Only HTML documents are parsed; other text types pass through.
+    fn applies_to(&self, content_type: &str) -> bool {
+        matches!(
+            essence(content_type).as_str(),
+            "text/html" | "application/xhtml+xml"
+        )
+    }
+
     fn sanitize(&self, html: &str) -> String {
         let mut result = html.to_string();
 
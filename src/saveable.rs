@@ -1,8 +1,10 @@
-//! The [`Saveable`] trait that user types implement to provide HTML content
-//! and naming information for persistence.
+//! The [`Saveable`] trait that user types implement to provide content and
+//! naming information for persistence.
 
-/// Trait implemented by user-defined request types that carry HTML content
-/// to be saved.
+use crate::storage::ObjectMetadata;
+
+/// Trait implemented by user-defined request types that carry HTML (or any
+/// other) content to be saved.
 ///
 /// The struct implementing this trait holds all metadata required to produce
 /// the storage key ([`name`](Saveable::name)) and the HTML body
@@ -32,12 +34,99 @@ pub trait Saveable: Send + 'static {
     /// Returns the raw HTML content to save.
     ///
     /// This content will be passed through the sanitizer pipeline (if any)
-    /// before being written to storage.
+    /// before being written to storage. Binary items override
+    /// [`content_bytes`](Saveable::content_bytes) instead.
     fn content(&self) -> &str;
 
     /// Generates the storage key (file path / object key) for this item.
     ///
-    /// Called by the background worker at flush time. If a prefix is
-    /// configured on the builder, it will be prepended automatically.
+    /// Called when the item is saved. If a prefix is configured on the
+    /// builder, it will be prepended automatically; with a
+    /// [key template](crate::HtmlSaverBuilder::key_template) the name is only
+    /// used where the template says `{name}`.
     fn name(&self) -> String;
+
+    /// MIME type of the content. Defaults to `"text/html"`.
+    ///
+    /// Sanitizers only run on text types they
+    /// [apply to](crate::Sanitizer::applies_to); binary content such as
+    /// `application/pdf` is stored untouched.
+    fn content_type(&self) -> &str {
+        "text/html"
+    }
+
+    /// Raw bytes to save. Defaults to the UTF-8 bytes of
+    /// [`content`](Saveable::content).
+    ///
+    /// Override this for binary payloads; `content` is then not used by the
+    /// worker and may return an empty string.
+    ///
+    /// ```
+    /// use html_saver::Saveable;
+    ///
+    /// struct Pdf { name: String, data: Vec<u8> }
+    ///
+    /// impl Saveable for Pdf {
+    ///     fn content(&self) -> &str { "" }
+    ///     fn content_bytes(&self) -> &[u8] { &self.data }
+    ///     fn content_type(&self) -> &str { "application/pdf" }
+    ///     fn name(&self) -> String { format!("{}.pdf", self.name) }
+    /// }
+    /// ```
+    fn content_bytes(&self) -> &[u8] {
+        self.content().as_bytes()
+    }
+
+    /// Metadata to store the object with, such as the source URL, the scrape
+    /// timestamp, the HTTP status or object tags.
+    ///
+    /// Defaults to the [`content_type`](Saveable::content_type) alone. Any
+    /// [`content_encoding`](ObjectMetadata::content_encoding) is overwritten by
+    /// the configured [`Compression`](crate::Compression).
+    ///
+    /// ```
+    /// use html_saver::{ObjectMetadata, Saveable};
+    ///
+    /// struct Page { url: String, status: u16, html: String }
+    ///
+    /// impl Saveable for Page {
+    ///     fn content(&self) -> &str { &self.html }
+    ///     fn name(&self) -> String { format!("{}.html", self.url.replace('/', "_")) }
+    ///     fn metadata(&self) -> ObjectMetadata {
+    ///         ObjectMetadata::new(self.content_type())
+    ///             .user_metadata("source-url", &self.url)
+    ///             .user_metadata("http-status", self.status.to_string())
+    ///     }
+    /// }
+    /// ```
+    fn metadata(&self) -> ObjectMetadata {
+        ObjectMetadata::new(self.content_type())
+    }
+
+    /// Value of a custom field of the
+    /// [key template](crate::HtmlSaverBuilder::key_template), such as `host`
+    /// in `{date:%Y/%m/%d}/{host}/{name}`.
+    ///
+    /// Defaults to `None` for every field; items without a value for a field
+    /// the template uses are rejected with [`SaveError::Key`](crate::SaveError::Key).
+    ///
+    /// ```
+    /// use html_saver::Saveable;
+    ///
+    /// struct Page { host: String, path: String, html: String }
+    ///
+    /// impl Saveable for Page {
+    ///     fn content(&self) -> &str { &self.html }
+    ///     fn name(&self) -> String { format!("{}.html", self.path.replace('/', "_")) }
+    ///     fn key_field(&self, field: &str) -> Option<String> {
+    ///         match field {
+    ///             "host" => Some(self.host.clone()),
+    ///             _ => None,
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn key_field(&self, _field: &str) -> Option<String> {
+        None
+    }
 }
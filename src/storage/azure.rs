@@ -0,0 +1,710 @@
+//! Azure Blob Storage backend (requires the `azure` feature).
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use sha2::Sha256;
+
+use crate::error::{HtmlSaverError, Result};
+use crate::storage::{ObjectMetadata, Storage};
+
+/// REST API version sent with every request.
+const API_VERSION: &str = "2021-08-06";
+
+/// Account name of the Azurite emulator.
+const DEV_ACCOUNT: &str = "devstoreaccount1";
+
+/// Well-known account key of the Azurite emulator.
+const DEV_ACCOUNT_KEY: &str =
+    "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+
+/// Blob endpoint of the Azurite emulator.
+const DEV_BLOB_ENDPOINT: &str = "http://127.0.0.1:10000/devstoreaccount1";
+
+/// How requests are authorized.
+#[derive(Clone)]
+enum Auth {
+    /// Shared Key: every request is signed with the account key.
+    SharedKey { account: String, key: Vec<u8> },
+    /// Shared access signature appended to every URL.
+    Sas(String),
+}
+
+/// Storage backend that uploads block blobs to an Azure Blob Storage
+/// container.
+///
+/// The [`ObjectMetadata`] of each item maps to the blob's content type,
+/// content encoding and cache control, blob metadata (`x-ms-meta-*`) and
+/// blob index tags. Azure metadata names must be valid C# identifiers, so
+/// `-` in user metadata names is replaced with `_`.
+///
+/// Bodies of at least
+/// [`block_upload_threshold`](AzureBlobStorage::block_upload_threshold) bytes
+/// are staged as separate blocks, up to
+/// [`block_concurrency`](AzureBlobStorage::block_concurrency) at a time, and
+/// committed with a single block list. Blocks of a failed upload are never
+/// committed and are discarded by the service.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use html_saver::AzureBlobStorage;
+///
+/// let storage = AzureBlobStorage::from_connection_string(
+///     "DefaultEndpointsProtocol=https;AccountName=acct;AccountKey=...;EndpointSuffix=core.windows.net",
+///     "pages",
+/// )?;
+/// // Azurite:
+/// let storage = AzureBlobStorage::from_connection_string("UseDevelopmentStorage=true", "pages")?;
+/// ```
+pub struct AzureBlobStorage {
+    client: reqwest::Client,
+    endpoint: String,
+    container: String,
+    auth: Auth,
+    block_upload_threshold: usize,
+    block_size: usize,
+    block_concurrency: usize,
+}
+
+impl AzureBlobStorage {
+    /// Largest number of blocks in a single blob.
+    pub const MAX_BLOCKS: usize = 50_000;
+
+    fn new(endpoint: impl Into<String>, container: impl Into<String>, auth: Auth) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            container: container.into(),
+            auth,
+            block_upload_threshold: 64 * 1024 * 1024,
+            block_size: 8 * 1024 * 1024,
+            block_concurrency: 4,
+        }
+    }
+
+    /// Create an `AzureBlobStorage` authorized with the storage account's
+    /// Shared Key.
+    ///
+    /// `account_key` is the base64 key shown in the Azure portal.
+    pub fn shared_key(
+        account: impl Into<String>,
+        account_key: &str,
+        container: impl Into<String>,
+    ) -> Result<Self> {
+        let account = account.into();
+        let key = BASE64
+            .decode(account_key.trim())
+            .map_err(|e| HtmlSaverError::Config(format!("Azure account key: {e}")))?;
+        Ok(Self::new(
+            format!("https://{account}.blob.core.windows.net"),
+            container,
+            Auth::SharedKey { account, key },
+        ))
+    }
+
+    /// Create an `AzureBlobStorage` authorized with a shared access
+    /// signature, e.g. `https://acct.blob.core.windows.net` and
+    /// `sv=2021-08-06&ss=b&...&sig=...`.
+    pub fn sas(
+        blob_endpoint: impl Into<String>,
+        sas_token: impl AsRef<str>,
+        container: impl Into<String>,
+    ) -> Self {
+        let token = sas_token.as_ref().trim_start_matches('?').to_string();
+        Self::new(blob_endpoint, container, Auth::Sas(token))
+    }
+
+    /// Create an `AzureBlobStorage` from a storage account connection
+    /// string.
+    ///
+    /// Supports account keys (`AccountName` + `AccountKey`), shared access
+    /// signatures (`BlobEndpoint` + `SharedAccessSignature`) and
+    /// `UseDevelopmentStorage=true` for Azurite.
+    pub fn from_connection_string(
+        connection_string: &str,
+        container: impl Into<String>,
+    ) -> Result<Self> {
+        let fields: HashMap<&str, &str> = connection_string
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .collect();
+        let field = |name: &str| fields.get(name).copied().filter(|v| !v.is_empty());
+
+        if field("UseDevelopmentStorage").is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+            return Ok(Self::shared_key(DEV_ACCOUNT, DEV_ACCOUNT_KEY, container)?
+                .endpoint(DEV_BLOB_ENDPOINT));
+        }
+
+        let endpoint = match (field("BlobEndpoint"), field("AccountName")) {
+            (Some(endpoint), _) => endpoint.to_string(),
+            (None, Some(account)) => format!(
+                "{}://{account}.blob.{}",
+                field("DefaultEndpointsProtocol").unwrap_or("https"),
+                field("EndpointSuffix").unwrap_or("core.windows.net")
+            ),
+            (None, None) => {
+                return Err(HtmlSaverError::Config(
+                    "Azure connection string needs AccountName or BlobEndpoint".into(),
+                ));
+            }
+        };
+
+        match (
+            field("SharedAccessSignature"),
+            field("AccountName"),
+            field("AccountKey"),
+        ) {
+            (Some(sas), _, _) => Ok(Self::sas(endpoint, sas, container)),
+            (None, Some(account), Some(key)) => {
+                Ok(Self::shared_key(account, key, container)?.endpoint(endpoint))
+            }
+            _ => Err(HtmlSaverError::Config(
+                "Azure connection string needs AccountKey or SharedAccessSignature".into(),
+            )),
+        }
+    }
+
+    /// Send requests to `endpoint` instead of the account's default blob
+    /// endpoint, e.g. `http://127.0.0.1:10000/devstoreaccount1` for Azurite.
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Stage bodies of at least `bytes` bytes as separate blocks.
+    pub fn block_upload_threshold(mut self, bytes: usize) -> Self {
+        self.block_upload_threshold = bytes;
+        self
+    }
+
+    /// Size of each staged block.
+    ///
+    /// Grown as needed so a body never needs more than
+    /// [`MAX_BLOCKS`](Self::MAX_BLOCKS) blocks.
+    pub fn block_size(mut self, bytes: usize) -> Self {
+        self.block_size = bytes.max(1);
+        self
+    }
+
+    /// Maximum number of blocks of one blob staged in parallel.
+    pub fn block_concurrency(mut self, blocks: usize) -> Self {
+        self.block_concurrency = blocks.max(1);
+        self
+    }
+}
+
+impl AzureBlobStorage {
+    /// Block size used for a body of `len` bytes.
+    fn block_size_for(&self, len: usize) -> usize {
+        self.block_size.max(len.div_ceil(Self::MAX_BLOCKS))
+    }
+
+    /// URL of a blob, with `query` parameters (unencoded) appended.
+    fn url(&self, key: &str, query: &[(&str, &str)]) -> String {
+        let mut url = format!("{}/{}/{}", self.endpoint, self.container, encode_path(key));
+        let mut params: Vec<String> = query
+            .iter()
+            .map(|(k, v)| format!("{k}={}", url_encode(v)))
+            .collect();
+        if let Auth::Sas(token) = &self.auth {
+            params.push(token.clone());
+        }
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
+        }
+        url
+    }
+
+    /// Send a `PUT` request, signing it when using Shared Key.
+    ///
+    /// Returns `false` if the request carried `If-None-Match: *` and the blob
+    /// already exists.
+    async fn send(
+        &self,
+        key: &str,
+        query: &[(&str, &str)],
+        mut headers: HeaderMap,
+        body: Vec<u8>,
+    ) -> Result<bool> {
+        let url = self.url(key, query);
+        let conditional = headers.contains_key(reqwest::header::IF_NONE_MATCH);
+        headers.insert("x-ms-version", HeaderValue::from_static(API_VERSION));
+        headers.insert(
+            "x-ms-date",
+            header_value(&httpdate::fmt_http_date(SystemTime::now()))?,
+        );
+        if let Auth::SharedKey {
+            account,
+            key: secret,
+        } = &self.auth
+        {
+            let path = url
+                .split_once("://")
+                .and_then(|(_, rest)| rest.find('/').map(|i| &rest[i..]))
+                .unwrap_or("/");
+            let path = path.split('?').next().unwrap_or(path);
+            let to_sign = string_to_sign("PUT", body.len(), &headers, account, path, query);
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+                .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+            mac.update(to_sign.as_bytes());
+            let signature = BASE64.encode(mac.finalize().into_bytes());
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                header_value(&format!("SharedKey {account}:{signature}"))?,
+            );
+        }
+
+        let response = self
+            .client
+            .put(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        let status = response.status();
+        if conditional
+            && matches!(
+                status,
+                reqwest::StatusCode::CONFLICT | reqwest::StatusCode::PRECONDITION_FAILED
+            )
+        {
+            return Ok(false);
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(HtmlSaverError::StorageUpload(
+                format!("Azure returned {status}: {message}").into(),
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Stage `content` as blocks and commit them with `headers`, returning
+    /// whether the blob was written.
+    async fn put_blocks(&self, key: &str, content: &[u8], headers: HeaderMap) -> Result<bool> {
+        let chunks = content.chunks(self.block_size_for(content.len()));
+        let ids: Vec<String> = (0..chunks.len()).map(block_id).collect();
+        let stages: Vec<_> = chunks
+            .zip(&ids)
+            .map(|(chunk, id)| self.put_block(key, id, chunk))
+            .collect();
+        futures::stream::iter(stages)
+            .buffer_unordered(self.block_concurrency)
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        let mut list = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for id in &ids {
+            list.push_str(&format!("<Latest>{id}</Latest>"));
+        }
+        list.push_str("</BlockList>");
+        self.send(key, &[("comp", "blocklist")], headers, list.into_bytes())
+            .await
+    }
+
+    async fn put_block(&self, key: &str, id: &str, chunk: &[u8]) -> Result<()> {
+        let query = [("comp", "block"), ("blockid", id)];
+        self.send(key, &query, HeaderMap::new(), chunk.to_vec())
+            .await
+            .map(|_| ())
+    }
+}
+
+impl AzureBlobStorage {
+    /// Upload a blob, with `if_absent` only if `key` does not exist yet
+    /// (`If-None-Match: *`). Returns whether it was written.
+    async fn upload(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+        if_absent: bool,
+    ) -> Result<bool> {
+        let mut headers = blob_headers(metadata)?;
+        if if_absent {
+            headers.insert(
+                reqwest::header::IF_NONE_MATCH,
+                HeaderValue::from_static("*"),
+            );
+        }
+        let written = if content.len() >= self.block_upload_threshold {
+            self.put_blocks(key, content, headers).await?
+        } else {
+            headers.insert("x-ms-blob-type", HeaderValue::from_static("BlockBlob"));
+            self.send(key, &[], headers, content.to_vec()).await?
+        };
+
+        if written {
+            tracing::debug!(
+                "Uploaded {} bytes to {}/{}/{}",
+                content.len(),
+                self.endpoint,
+                self.container,
+                key
+            );
+        } else {
+            tracing::debug!(
+                "Not replacing existing {}/{}/{}",
+                self.endpoint,
+                self.container,
+                key
+            );
+        }
+        Ok(written)
+    }
+}
+
+impl Storage for AzureBlobStorage {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.upload(key, content, metadata, false).await.map(|_| ())
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        self.upload(key, content, metadata, true).await
+    }
+}
+
+/// Fixed-width block IDs, as all IDs of a blob must have the same length.
+fn block_id(index: usize) -> String {
+    BASE64.encode(format!("{index:08}"))
+}
+
+/// Blob properties, metadata and tags sent with Put Blob or Put Block List.
+fn blob_headers(metadata: &ObjectMetadata) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-ms-blob-content-type",
+        header_value(&metadata.content_type)?,
+    );
+    if let Some(encoding) = &metadata.content_encoding {
+        headers.insert("x-ms-blob-content-encoding", header_value(encoding)?);
+    }
+    if let Some(cache_control) = &metadata.cache_control {
+        headers.insert("x-ms-blob-cache-control", header_value(cache_control)?);
+    }
+    for (name, value) in &metadata.user_metadata {
+        let name = format!("x-ms-meta-{}", name.replace('-', "_"));
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        headers.insert(name, header_value(value)?);
+    }
+    if !metadata.tags.is_empty() {
+        headers.insert("x-ms-tags", header_value(&encode_tags(&metadata.tags))?);
+    }
+    Ok(headers)
+}
+
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))
+}
+
+/// Build the Shared Key string-to-sign of a request.
+///
+/// `path` is the URL path as sent, including the container; `query` holds
+/// the unencoded query parameters.
+fn string_to_sign(
+    verb: &str,
+    content_length: usize,
+    headers: &HeaderMap,
+    account: &str,
+    path: &str,
+    query: &[(&str, &str)],
+) -> String {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    let content_length = if content_length == 0 {
+        String::new()
+    } else {
+        content_length.to_string()
+    };
+
+    let mut canonical_headers: Vec<(String, &str)> = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                value.to_str().unwrap_or_default(),
+            )
+        })
+        .collect();
+    canonical_headers.sort();
+
+    let mut canonical_query: Vec<(String, &str)> = query
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), *value))
+        .collect();
+    canonical_query.sort();
+
+    let mut out = format!(
+        "{verb}\n{}\n{}\n{content_length}\n{}\n{}\n\n{}\n{}\n{}\n{}\n{}\n",
+        header("content-encoding"),
+        header("content-language"),
+        header("content-md5"),
+        header("content-type"),
+        header("if-modified-since"),
+        header("if-match"),
+        header("if-none-match"),
+        header("if-unmodified-since"),
+        header("range"),
+    );
+    for (name, value) in canonical_headers {
+        out.push_str(&format!("{name}:{}\n", value.trim()));
+    }
+    out.push_str(&format!("/{account}{path}"));
+    for (name, value) in canonical_query {
+        out.push_str(&format!("\n{name}:{value}"));
+    }
+    out
+}
+
+/// Encode blob index tags as the query string expected by `x-ms-tags`.
+fn encode_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent-encode a blob name, keeping `/` separators.
+fn encode_path(key: &str) -> String {
+    key.split('/').map(url_encode).collect::<Vec<_>>().join("/")
+}
+
+fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header, header_regex, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn azurite(server: &MockServer) -> AzureBlobStorage {
+        AzureBlobStorage::from_connection_string("UseDevelopmentStorage=true", "pages")
+            .unwrap()
+            .endpoint(format!("{}/{DEV_ACCOUNT}", server.uri()))
+    }
+
+    #[test]
+    fn connection_strings_are_parsed() {
+        let storage = AzureBlobStorage::from_connection_string(
+            "DefaultEndpointsProtocol=https;AccountName=acct;AccountKey=a2V5;EndpointSuffix=core.windows.net",
+            "pages",
+        )
+        .unwrap();
+        assert_eq!(storage.endpoint, "https://acct.blob.core.windows.net");
+        assert!(
+            matches!(storage.auth, Auth::SharedKey { ref account, ref key } if account == "acct" && key == b"key")
+        );
+
+        let storage = AzureBlobStorage::from_connection_string(
+            "BlobEndpoint=https://acct.blob.core.windows.net/;SharedAccessSignature=sv=2021-08-06&sig=abc",
+            "pages",
+        )
+        .unwrap();
+        assert_eq!(storage.endpoint, "https://acct.blob.core.windows.net");
+        assert!(matches!(storage.auth, Auth::Sas(ref token) if token == "sv=2021-08-06&sig=abc"));
+
+        let storage =
+            AzureBlobStorage::from_connection_string("UseDevelopmentStorage=true", "pages")
+                .unwrap();
+        assert_eq!(storage.endpoint, DEV_BLOB_ENDPOINT);
+
+        assert!(matches!(
+            AzureBlobStorage::from_connection_string("AccountName=acct", "pages"),
+            Err(HtmlSaverError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn string_to_sign_is_canonical() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ms-version", HeaderValue::from_static(API_VERSION));
+        headers.insert(
+            "x-ms-date",
+            HeaderValue::from_static("Fri, 16 Oct 2026 12:00:00 GMT"),
+        );
+        headers.insert("x-ms-blob-type", HeaderValue::from_static("BlockBlob"));
+        let to_sign = string_to_sign(
+            "PUT",
+            9,
+            &headers,
+            "acct",
+            "/pages/a%20b.html",
+            &[("comp", "block"), ("blockid", "MDAwMDAwMDA=")],
+        );
+        assert_eq!(
+            to_sign,
+            "PUT\n\n\n9\n\n\n\n\n\n\n\n\n\
+             x-ms-blob-type:BlockBlob\n\
+             x-ms-date:Fri, 16 Oct 2026 12:00:00 GMT\n\
+             x-ms-version:2021-08-06\n\
+             /acct/pages/a%20b.html\nblockid:MDAwMDAwMDA=\ncomp:block"
+        );
+    }
+
+    #[tokio::test]
+    async fn small_bodies_use_put_blob() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/devstoreaccount1/pages/dir/index.html"))
+            .and(header("x-ms-blob-type", "BlockBlob"))
+            .and(header("x-ms-blob-content-type", "text/html"))
+            .and(header("x-ms-meta-source_url", "https://example.com/"))
+            .and(header("x-ms-tags", "crawl=daily"))
+            .and(header_regex(
+                "authorization",
+                "^SharedKey devstoreaccount1:",
+            ))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = ObjectMetadata::new("text/html")
+            .user_metadata("source-url", "https://example.com/")
+            .tag("crawl", "daily");
+        azurite(&server)
+            .put("dir/index.html", b"<p>hi</p>", &metadata)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_staged_as_blocks() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(query_param("comp", "block"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(query_param("comp", "blocklist"))
+            .and(header("x-ms-blob-content-type", "text/html"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let storage = azurite(&server).block_upload_threshold(8).block_size(4);
+        storage
+            .put(
+                "index.html",
+                b"0123456789",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let list = String::from_utf8_lossy(&requests.last().unwrap().body).into_owned();
+        let ids: Vec<_> = (0..3).map(block_id).collect();
+        assert!(
+            list.contains(&format!(
+                "<Latest>{}</Latest><Latest>{}</Latest><Latest>{}</Latest>",
+                ids[0], ids[1], ids[2]
+            )),
+            "{list}"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_blocks_are_not_committed() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(query_param("comp", "block"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(query_param("comp", "blocklist"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let storage = azurite(&server).block_upload_threshold(8).block_size(4);
+        let result = storage
+            .put(
+                "index.html",
+                b"0123456789",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await;
+        assert!(matches!(result, Err(HtmlSaverError::StorageUpload(_))));
+    }
+
+    #[tokio::test]
+    async fn sas_token_is_appended_instead_of_signing() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/pages/index.html"))
+            .and(query_param("sig", "abc"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        AzureBlobStorage::sas(server.uri(), "?sv=2021-08-06&sig=abc", "pages")
+            .put(
+                "index.html",
+                b"<p>hi</p>",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await
+            .unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests[0].headers.contains_key("authorization"));
+    }
+
+    #[tokio::test]
+    async fn put_if_absent_sends_if_none_match() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(header("if-none-match", "*"))
+            .respond_with(ResponseTemplate::new(409).set_body_string("BlobAlreadyExists"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let written = azurite(&server)
+            .put_if_absent(
+                "index.html",
+                b"<p>hi</p>",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await
+            .unwrap();
+        assert!(!written);
+    }
+}
@@ -0,0 +1,197 @@
+//! Fault-injecting storage wrapper for tests.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures::stream::{self, Stream, StreamExt};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::storage::{ObjectInfo, ObjectMetadata, Storage, StoredObject};
+
+/// Storage wrapper that injects failures and latency into another backend,
+/// for testing retries, dead-lettering and backpressure.
+///
+/// Failed writes return [`HtmlSaverError::StorageUpload`], which the default
+/// [`RetryPolicy`](crate::RetryPolicy) treats as retryable, and do not reach
+/// the inner storage. Reads, listings and deletes are only delayed.
+///
+/// Clones share their counters.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use html_saver::{FaultyStorage, MemoryStorage};
+///
+/// let storage = FaultyStorage::new(MemoryStorage::new())
+///     .fail_every(3)
+///     .fail_key("poison.html")
+///     .latency(Duration::from_millis(5));
+/// ```
+#[derive(Clone, Debug)]
+pub struct FaultyStorage<S> {
+    inner: S,
+    fail_every: u64,
+    fail_keys: HashSet<String>,
+    latency: Duration,
+    puts: Arc<AtomicU64>,
+    failures: Arc<AtomicU64>,
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    /// Wrap `inner` without injecting anything yet.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            fail_every: 0,
+            fail_keys: HashSet::new(),
+            latency: Duration::ZERO,
+            puts: Arc::new(AtomicU64::new(0)),
+            failures: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Fail every `n`th write: the `n`th, `2n`th, and so on. `1` fails every
+    /// write, `0` (the default) none.
+    pub fn fail_every(mut self, n: u64) -> Self {
+        self.fail_every = n;
+        self
+    }
+
+    /// Fail every write to `key`.
+    pub fn fail_key(mut self, key: impl Into<String>) -> Self {
+        self.fail_keys.insert(key.into());
+        self
+    }
+
+    /// Sleep for `latency` before every operation.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The wrapped storage.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of writes attempted so far, including failed ones.
+    pub fn puts(&self) -> u64 {
+        self.puts.load(Ordering::Relaxed)
+    }
+
+    /// Number of writes failed on purpose so far.
+    pub fn injected_failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    async fn delay(&self) {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+    }
+
+    /// Delay a write and decide whether it fails.
+    async fn before_write(&self, key: &str) -> Result<()> {
+        self.delay().await;
+        let n = self.puts.fetch_add(1, Ordering::Relaxed) + 1;
+        // `is_multiple_of(0)` is false for every `n >= 1`.
+        let fail = n.is_multiple_of(self.fail_every) || self.fail_keys.contains(key);
+        if fail {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return Err(HtmlSaverError::StorageUpload(
+                format!("injected failure for {key} (write #{n})").into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.before_write(key).await?;
+        self.inner.put(key, content, metadata).await
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        self.before_write(key).await?;
+        self.inner.put_if_absent(key, content, metadata).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        self.delay().await;
+        self.inner.get(key).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        self.delay().await;
+        self.inner.exists(key).await
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> impl Stream<Item = Result<ObjectInfo>> + Send + 'a {
+        // Only the start of the listing is delayed.
+        stream::once(self.delay()).flat_map(move |()| self.inner.list(prefix))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.delay().await;
+        self.inner.delete(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    #[tokio::test]
+    async fn fails_every_nth_write() {
+        let storage = FaultyStorage::new(MemoryStorage::new()).fail_every(3);
+        let mut results = Vec::new();
+        for i in 0..6 {
+            let key = format!("{i}.html");
+            results.push(storage.put(&key, b"", &html()).await.is_ok());
+        }
+        assert_eq!(results, [true, true, false, true, true, false]);
+        assert_eq!(storage.puts(), 6);
+        assert_eq!(storage.injected_failures(), 2);
+        assert_eq!(storage.inner().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn fails_particular_keys() {
+        let storage = FaultyStorage::new(MemoryStorage::new()).fail_key("poison.html");
+        assert!(matches!(
+            storage.put("poison.html", b"", &html()).await,
+            Err(HtmlSaverError::StorageUpload(_))
+        ));
+        assert!(
+            storage
+                .put_if_absent("poison.html", b"", &html())
+                .await
+                .is_err()
+        );
+        storage.put("ok.html", b"", &html()).await.unwrap();
+        assert_eq!(storage.inner().keys(), ["ok.html"]);
+    }
+
+    #[tokio::test]
+    async fn adds_latency() {
+        let storage = FaultyStorage::new(MemoryStorage::new()).latency(Duration::from_millis(20));
+        let start = std::time::Instant::now();
+        storage.put("a.html", b"", &html()).await.unwrap();
+        storage.get("a.html").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -1,14 +1,37 @@
 //! Filesystem storage backend.
 
-use std::path::PathBuf;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use futures::stream::{self, Stream};
+use tokio::sync::OnceCell;
 
 use crate::error::{HtmlSaverError, Result};
-use crate::storage::Storage;
+use crate::key::validate_key;
+use crate::storage::{ObjectInfo, ObjectMetadata, Storage, StoredObject};
 
 /// Storage backend that writes files to the local filesystem.
 ///
 /// Intermediate directories are created automatically. The `key` provided to
-/// [`Storage::put`] is joined with the base directory to form the final path.
+/// [`Storage::put`] is joined with the base directory to form the final path;
+/// keys that could escape it, such as `../x` or `/etc/x`, are rejected with
+/// [`HtmlSaverError::InvalidKey`] (see [`key`](crate::key)).
+/// Content is written as-is, so compressed documents end up on disk
+/// compressed, under a key carrying the codec's extension.
+///
+/// Writes are atomic: content goes to a hidden temp file in the target
+/// directory, which is fsynced and renamed into place, so a crash never
+/// leaves a truncated file under the final name. Temp files orphaned by a
+/// crash are removed before the first write of each `FsStorage` (see
+/// [`cleanup_temp_files`](Self::cleanup_temp_files)).
+///
+/// With [`write_metadata`](Self::write_metadata) enabled, the
+/// [`ObjectMetadata`] of every file is written next to it as
+/// `<key>.meta.json`. [`get`](Storage::get) returns it when present, and
+/// [`list`](Storage::list) skips the sidecars.
 ///
 /// # Example
 ///
@@ -19,32 +42,568 @@ use crate::storage::Storage;
 /// ```
 pub struct FsStorage {
     base_dir: PathBuf,
+    write_metadata: bool,
+    sync_dir: bool,
+    cleanup_temp_files: bool,
+    cleaned_up: OnceCell<()>,
 }
 
 impl FsStorage {
+    /// Suffix appended to a key to form the name of its metadata sidecar.
+    pub const METADATA_SUFFIX: &str = ".meta.json";
+
+    /// Suffix of the temp files that writes go through before being renamed
+    /// into place.
+    pub const TEMP_SUFFIX: &str = ".html_saver.tmp";
+
     /// Create a new `FsStorage` rooted at the given directory.
     pub fn new(base_dir: impl Into<PathBuf>) -> Self {
         Self {
             base_dir: base_dir.into(),
+            write_metadata: false,
+            sync_dir: false,
+            cleanup_temp_files: true,
+            cleaned_up: OnceCell::new(),
+        }
+    }
+
+    /// Write the [`ObjectMetadata`] of every file to a
+    /// `<key>.meta.json` sidecar. Disabled by default.
+    pub fn write_metadata(mut self, enabled: bool) -> Self {
+        self.write_metadata = enabled;
+        self
+    }
+
+    /// Also fsync the directory after renaming a file into place, so the new
+    /// entry itself survives a power loss. Disabled by default; has no effect
+    /// on platforms other than Unix.
+    pub fn sync_dir(mut self, enabled: bool) -> Self {
+        self.sync_dir = enabled;
+        self
+    }
+
+    /// Remove temp files left behind by crashed writes before the first write
+    /// (default: `true`).
+    ///
+    /// Temp files of writes in flight in this process are never touched, but
+    /// those of other processes are: disable this when several processes
+    /// write to the same directory, and call
+    /// [`remove_temp_files`](Self::remove_temp_files) when none is running.
+    pub fn cleanup_temp_files(mut self, enabled: bool) -> Self {
+        self.cleanup_temp_files = enabled;
+        self
+    }
+
+    /// Remove the temp files of writes not made by this process anywhere
+    /// under the base directory, returning how many were removed.
+    pub async fn remove_temp_files(&self) -> Result<usize> {
+        let base_dir = self.base_dir.clone();
+        tokio::task::spawn_blocking(move || remove_orphans(&base_dir))
+            .await
+            .map_err(storage_error)?
+            .map_err(storage_error)
+    }
+
+    /// Write `key` atomically, replacing an existing file only if `replace`
+    /// is set. Returns whether the file was written.
+    async fn write(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+        replace: bool,
+    ) -> Result<bool> {
+        validate_key(key)?;
+        if self.cleanup_temp_files {
+            self.cleaned_up
+                .get_or_init(|| async {
+                    match self.remove_temp_files().await {
+                        Ok(0) => {}
+                        Ok(n) => tracing::info!(
+                            "Removed {n} orphaned temp files from {}",
+                            self.base_dir.display()
+                        ),
+                        Err(e) => tracing::warn!(
+                            "Failed to remove orphaned temp files from {}: {e}",
+                            self.base_dir.display()
+                        ),
+                    }
+                })
+                .await;
+        }
+
+        let path = self.base_dir.join(key);
+        let sidecar = if self.write_metadata {
+            let sidecar = serde_json::to_vec_pretty(metadata)
+                .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+            Some((self.sidecar_path(key), sidecar))
+        } else {
+            None
+        };
+
+        let len = content.len();
+        let content = content.to_vec();
+        let sync_dir = self.sync_dir;
+        let target = path.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if !write_atomic(&target, &content, sync_dir, replace)? {
+                return Ok(false);
+            }
+            if let Some((sidecar_path, sidecar)) = sidecar {
+                write_atomic(&sidecar_path, &sidecar, sync_dir, true)?;
+            }
+            io::Result::Ok(true)
+        })
+        .await
+        .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?
+        .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+
+        if written {
+            tracing::debug!("Wrote {len} bytes to {}", path.display());
+        } else {
+            tracing::debug!("Not replacing existing {}", path.display());
         }
+        Ok(written)
+    }
+
+    /// Path of the metadata sidecar of `key`.
+    fn sidecar_path(&self, key: &str) -> PathBuf {
+        self.base_dir
+            .join(format!("{key}{}", Self::METADATA_SUFFIX))
+    }
+}
+
+fn storage_error(e: impl std::error::Error + Send + Sync + 'static) -> HtmlSaverError {
+    HtmlSaverError::Storage(Box::new(e))
+}
+
+/// Random ID of this process, part of the name of every temp file it writes
+/// so that cleanup never removes a write in flight.
+fn run_id() -> u64 {
+    static RUN_ID: OnceLock<u64> = OnceLock::new();
+    *RUN_ID.get_or_init(|| fastrand::u64(..))
+}
+
+/// Temp file for a write to `path`: `.<name>.<run id>-<random>.html_saver.tmp`
+/// in the same directory, so the rename never crosses filesystems.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        ".{name}.{:016x}-{:08x}{}",
+        run_id(),
+        fastrand::u32(..),
+        FsStorage::TEMP_SUFFIX
+    ))
+}
+
+/// Write `content` to a temp file, fsync it and rename it to `path`.
+///
+/// Without `replace`, an existing file at `path` is left alone and `false`
+/// returned: the temp file is hard-linked into place, which fails if `path`
+/// exists just like opening it with `create_new` would.
+fn write_atomic(path: &Path, content: &[u8], sync_dir: bool, replace: bool) -> io::Result<bool> {
+    let tmp = temp_path(path);
+    let written = create_new(&tmp, content).and_then(|_| {
+        if replace {
+            return fs::rename(&tmp, path).map(|()| true);
+        }
+        match fs::hard_link(&tmp, path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            // No hard links on this filesystem: still never replace a file,
+            // at the cost of atomicity.
+            Err(_) => create_new(path, content),
+        }
+    });
+    if !replace || written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    let written = written?;
+    if written && sync_dir {
+        sync_parent(path)?;
+    }
+    Ok(written)
+}
+
+/// Write and fsync a file that must not exist yet, returning `false` if it
+/// does.
+fn create_new(path: &Path, content: &[u8]) -> io::Result<bool> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Remove temp files not written by this process under `dir`.
+fn remove_orphans(dir: &Path) -> io::Result<usize> {
+    let own = format!(".{:016x}-", run_id());
+    let mut removed = 0;
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if file_type.is_file()
+                && name.starts_with('.')
+                && name.ends_with(FsStorage::TEMP_SUFFIX)
+                && !name.contains(&own)
+            {
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => removed += 1,
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Read `path`, or `None` if it does not exist.
+async fn read_if_exists(path: &std::path::Path) -> Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(storage_error(e)),
+    }
+}
+
+/// Remove `path`, succeeding if it does not exist.
+async fn remove_if_exists(path: &std::path::Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(storage_error(e)),
+        _ => Ok(()),
     }
 }
 
 impl Storage for FsStorage {
-    async fn put(&self, key: &str, content: &[u8], _content_type: &str) -> Result<()> {
-        let path = self.base_dir.join(key);
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.write(key, content, metadata, true).await.map(|_| ())
+    }
 
-        if let Some(parent) = path.parent() {
-            tokio::fs::create_dir_all(parent)
-                .await
-                .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        self.write(key, content, metadata, false).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        validate_key(key)?;
+        let Some(content) = read_if_exists(&self.base_dir.join(key)).await? else {
+            return Ok(None);
+        };
+        let metadata = match read_if_exists(&self.sidecar_path(key)).await? {
+            Some(sidecar) => Some(serde_json::from_slice(&sidecar).map_err(storage_error)?),
+            None => None,
+        };
+        Ok(Some(StoredObject::new(content, metadata)))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        validate_key(key)?;
+        match tokio::fs::metadata(self.base_dir.join(key)).await {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(storage_error(e)),
         }
+    }
 
-        tokio::fs::write(&path, content)
-            .await
-            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+    fn list<'a>(&'a self, prefix: &'a str) -> impl Stream<Item = Result<ObjectInfo>> + Send + 'a {
+        // Walk from the deepest directory named by the prefix, e.g. `2026/01`
+        // for `2026/01/page`; directories hold keys relative to the base.
+        let start = prefix.rfind('/').map_or("", |i| &prefix[..i]);
+        let valid = start.is_empty() || validate_key(start).is_ok();
+        let state = (vec![start.to_string()], VecDeque::new());
+        stream::try_unfold(state, move |(mut dirs, mut found)| async move {
+            if !valid {
+                validate_key(prefix)?;
+            }
+            loop {
+                if let Some(info) = found.pop_front() {
+                    return Ok(Some((info, (dirs, found))));
+                }
+                let Some(dir) = dirs.pop() else {
+                    return Ok(None);
+                };
+                let mut entries = match tokio::fs::read_dir(self.base_dir.join(&dir)).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(storage_error(e)),
+                };
+                while let Some(entry) = entries.next_entry().await.map_err(storage_error)? {
+                    // Names that are not UTF-8 cannot have been written as keys.
+                    let Ok(name) = entry.file_name().into_string() else {
+                        continue;
+                    };
+                    let key = if dir.is_empty() {
+                        name
+                    } else {
+                        format!("{dir}/{name}")
+                    };
+                    if !key.starts_with(prefix) {
+                        continue;
+                    }
+                    let file_type = entry.file_type().await.map_err(storage_error)?;
+                    if file_type.is_dir() {
+                        dirs.push(key);
+                    } else if file_type.is_file()
+                        && !key.ends_with(Self::METADATA_SUFFIX)
+                        && !key.ends_with(Self::TEMP_SUFFIX)
+                    {
+                        let metadata = entry.metadata().await.map_err(storage_error)?;
+                        found.push_back(ObjectInfo::new(
+                            key,
+                            metadata.len(),
+                            metadata.modified().ok(),
+                        ));
+                    }
+                }
+            }
+        })
+    }
 
-        tracing::debug!("Wrote {} bytes to {}", content.len(), path.display());
+    async fn delete(&self, key: &str) -> Result<()> {
+        validate_key(key)?;
+        remove_if_exists(&self.base_dir.join(key)).await?;
+        remove_if_exists(&self.sidecar_path(key)).await?;
+        tracing::debug!("Deleted {}", self.base_dir.join(key).display());
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    async fn keys(storage: &FsStorage, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = storage
+            .list(prefix)
+            .map_ok(|info| info.key)
+            .try_collect()
+            .await
+            .unwrap();
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn get_returns_content_and_sidecar_metadata() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path()).write_metadata(true);
+        let metadata = ObjectMetadata::new("text/html").user_metadata("source-url", "https://a/");
+        storage
+            .put("a/page.html", b"<p>hi</p>", &metadata)
+            .await
+            .unwrap();
+
+        let object = storage.get("a/page.html").await.unwrap().unwrap();
+        assert_eq!(object.content, b"<p>hi</p>");
+        assert_eq!(object.metadata, Some(metadata));
+        assert!(storage.exists("a/page.html").await.unwrap());
+        assert!(storage.get("a/missing.html").await.unwrap().is_none());
+        assert!(!storage.exists("a").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn list_matches_key_prefixes_and_skips_sidecars() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path()).write_metadata(true);
+        let metadata = ObjectMetadata::new("text/html");
+        for key in [
+            "2026/01/a.html",
+            "2026/01/b.html",
+            "2026/02/c.html",
+            "2027/d.html",
+        ] {
+            storage.put(key, b"<p>hi</p>", &metadata).await.unwrap();
+        }
+
+        assert_eq!(keys(&storage, "").await.len(), 4);
+        assert_eq!(
+            keys(&storage, "2026/0").await,
+            ["2026/01/a.html", "2026/01/b.html", "2026/02/c.html"]
+        );
+        assert_eq!(keys(&storage, "2026/01/b").await, ["2026/01/b.html"]);
+        assert!(keys(&storage, "2028/").await.is_empty());
+
+        let info = storage.list("2027/").try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(info[0].size, 9);
+        assert!(info[0].last_modified.is_some());
+    }
+
+    #[tokio::test]
+    async fn delete_removes_the_file_and_its_sidecar() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path()).write_metadata(true);
+        storage
+            .put("page.html", b"<p>hi</p>", &ObjectMetadata::new("text/html"))
+            .await
+            .unwrap();
+
+        storage.delete("page.html").await.unwrap();
+        assert!(!storage.exists("page.html").await.unwrap());
+        assert!(!tmp.path().join("page.html.meta.json").exists());
+        storage.delete("page.html").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_leave_no_temp_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path())
+            .write_metadata(true)
+            .sync_dir(true);
+        let metadata = ObjectMetadata::new("text/html");
+        storage.put("a/page.html", b"old", &metadata).await.unwrap();
+        storage.put("a/page.html", b"new", &metadata).await.unwrap();
+
+        let mut names: Vec<_> = std::fs::read_dir(tmp.path().join("a"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["page.html", "page.html.meta.json"]);
+        assert_eq!(
+            std::fs::read(tmp.path().join("a/page.html")).unwrap(),
+            b"new"
+        );
+    }
+
+    #[tokio::test]
+    async fn orphaned_temp_files_are_removed_before_the_first_write() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(tmp.path().join("a")).unwrap();
+        let orphan = tmp
+            .path()
+            .join("a/.page.html.0000000000000000-00000000.html_saver.tmp");
+        let in_flight = temp_path(&tmp.path().join("other.html"));
+        let unrelated = tmp.path().join("notes.tmp");
+        for path in [&orphan, &in_flight, &unrelated] {
+            std::fs::write(path, b"partial").unwrap();
+        }
+
+        let storage = FsStorage::new(tmp.path());
+        assert!(keys(&storage, "").await.contains(&"notes.tmp".to_string()));
+        assert_eq!(keys(&storage, "a/").await, Vec::<String>::new());
+        storage
+            .put("page.html", b"<p>hi</p>", &ObjectMetadata::new("text/html"))
+            .await
+            .unwrap();
+
+        assert!(!orphan.exists());
+        assert!(in_flight.exists());
+        assert!(unrelated.exists());
+    }
+
+    #[tokio::test]
+    async fn cleanup_can_be_disabled() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let orphan = tmp
+            .path()
+            .join(".page.html.0000000000000000-00000000.html_saver.tmp");
+        std::fs::write(&orphan, b"partial").unwrap();
+
+        let storage = FsStorage::new(tmp.path()).cleanup_temp_files(false);
+        storage
+            .put("page.html", b"<p>hi</p>", &ObjectMetadata::new("text/html"))
+            .await
+            .unwrap();
+        assert!(orphan.exists());
+        assert_eq!(storage.remove_temp_files().await.unwrap(), 1);
+        assert!(!orphan.exists());
+    }
+
+    #[tokio::test]
+    async fn keys_escaping_the_base_directory_are_rejected() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path().join("base"));
+        let metadata = ObjectMetadata::new("text/html");
+
+        for key in [
+            "../escaped.html",
+            "/tmp/escaped.html",
+            "a/../../escaped.html",
+        ] {
+            let result = storage.put(key, b"<p>hi</p>", &metadata).await;
+            assert!(
+                matches!(result, Err(HtmlSaverError::InvalidKey { .. })),
+                "{key}"
+            );
+            assert!(matches!(
+                storage.get(key).await,
+                Err(HtmlSaverError::InvalidKey { .. })
+            ));
+            assert!(matches!(
+                storage.delete(key).await,
+                Err(HtmlSaverError::InvalidKey { .. })
+            ));
+        }
+        assert!(!tmp.path().join("escaped.html").exists());
+        assert!(matches!(
+            storage.list("../").try_collect::<Vec<_>>().await,
+            Err(HtmlSaverError::InvalidKey { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_if_absent_keeps_existing_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let storage = FsStorage::new(tmp.path()).write_metadata(true);
+        let html = ObjectMetadata::new("text/html");
+        let json = ObjectMetadata::new("application/json");
+
+        assert!(
+            storage
+                .put_if_absent("a/page", b"first", &html)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .put_if_absent("a/page", b"second", &json)
+                .await
+                .unwrap()
+        );
+
+        let object = storage.get("a/page").await.unwrap().unwrap();
+        assert_eq!(object.content, b"first");
+        assert_eq!(object.metadata, Some(html));
+        assert_eq!(keys(&storage, "").await, ["a/page"]);
+    }
+}
@@ -0,0 +1,340 @@
+//! Google Cloud Storage backend (requires the `gcs` feature).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use gcp_auth::{CustomServiceAccount, MetadataServiceAccount, TokenProvider};
+use serde::Serialize;
+
+use crate::error::{HtmlSaverError, Result};
+use crate::storage::{ObjectMetadata, Storage};
+
+/// Public Cloud Storage endpoint.
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// OAuth scope needed to create objects.
+const SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+/// Environment variable pointing at a storage emulator such as
+/// [fake-gcs-server](https://github.com/fsouza/fake-gcs-server).
+const EMULATOR_HOST_VAR: &str = "STORAGE_EMULATOR_HOST";
+
+/// Storage backend that uploads objects to a Google Cloud Storage bucket
+/// through the JSON API.
+///
+/// The [`ObjectMetadata`] of each item maps to the object's `contentType`,
+/// `contentEncoding`, `cacheControl` and custom `metadata`. Cloud Storage has
+/// no object tags, so [`ObjectMetadata::tags`] are not stored.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use html_saver::GcsStorage;
+///
+/// // Service account key file:
+/// let storage = GcsStorage::from_service_account_file("sa.json", "my-bucket")?;
+/// // Application default credentials or the metadata server:
+/// let storage = GcsStorage::from_env("my-bucket").await?;
+/// // fake-gcs-server:
+/// let storage = GcsStorage::emulator("http://localhost:4443", "my-bucket");
+/// ```
+pub struct GcsStorage {
+    client: reqwest::Client,
+    bucket: String,
+    endpoint: String,
+    auth: Option<Arc<dyn TokenProvider>>,
+}
+
+impl GcsStorage {
+    fn new(bucket: impl Into<String>, auth: Option<Arc<dyn TokenProvider>>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            bucket: bucket.into(),
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            auth,
+        }
+    }
+
+    /// Create a `GcsStorage` with credentials discovered from the
+    /// environment.
+    ///
+    /// Tries, in order, the service account key in
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, gcloud application default
+    /// credentials, the GCE metadata server and the `gcloud` CLI. If
+    /// `STORAGE_EMULATOR_HOST` is set, connects to that emulator without
+    /// credentials instead.
+    pub async fn from_env(bucket: impl Into<String>) -> Result<Self> {
+        if let Ok(host) = std::env::var(EMULATOR_HOST_VAR) {
+            return Ok(Self::emulator(host, bucket));
+        }
+        let auth = gcp_auth::provider().await.map_err(credentials_error)?;
+        Ok(Self::new(bucket, Some(auth)))
+    }
+
+    /// Create a `GcsStorage` authenticated with a service account key file.
+    pub fn from_service_account_file(
+        path: impl AsRef<Path>,
+        bucket: impl Into<String>,
+    ) -> Result<Self> {
+        let account = CustomServiceAccount::from_file(path).map_err(credentials_error)?;
+        Ok(Self::new(bucket, Some(Arc::new(account))))
+    }
+
+    /// Create a `GcsStorage` authenticated with the contents of a service
+    /// account key file.
+    pub fn from_service_account_json(json: &str, bucket: impl Into<String>) -> Result<Self> {
+        let account = CustomServiceAccount::from_json(json).map_err(credentials_error)?;
+        Ok(Self::new(bucket, Some(Arc::new(account))))
+    }
+
+    /// Create a `GcsStorage` authenticated as the default service account of
+    /// the GCE metadata server.
+    pub async fn from_metadata_server(bucket: impl Into<String>) -> Result<Self> {
+        let account = MetadataServiceAccount::new()
+            .await
+            .map_err(credentials_error)?;
+        Ok(Self::new(bucket, Some(Arc::new(account))))
+    }
+
+    /// Create an unauthenticated `GcsStorage` for a storage emulator such as
+    /// fake-gcs-server, e.g. `http://localhost:4443`.
+    pub fn emulator(endpoint: impl Into<String>, bucket: impl Into<String>) -> Self {
+        Self::new(bucket, None).endpoint(endpoint)
+    }
+
+    /// Send requests to `endpoint` instead of `https://storage.googleapis.com`.
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        let endpoint = endpoint.into();
+        let endpoint = if endpoint.contains("://") {
+            endpoint
+        } else {
+            // `STORAGE_EMULATOR_HOST` is commonly given as `host:port`.
+            format!("http://{endpoint}")
+        };
+        self.endpoint = endpoint.trim_end_matches('/').to_string();
+        self
+    }
+}
+
+fn credentials_error(e: gcp_auth::Error) -> HtmlSaverError {
+    HtmlSaverError::Config(format!("GCS credentials: {e}"))
+}
+
+/// Object resource sent in the first part of a multipart upload.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Object<'a> {
+    name: &'a str,
+    content_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_encoding: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+}
+
+/// Build a `multipart/related` body carrying the object resource and its
+/// content, returning the body and its boundary.
+fn multipart_body(key: &str, content: &[u8], metadata: &ObjectMetadata) -> (Vec<u8>, String) {
+    let boundary = format!(
+        "html_saver_{:016x}{:016x}",
+        fastrand::u64(..),
+        fastrand::u64(..)
+    );
+    let object = serde_json::to_string(&Object {
+        name: key,
+        content_type: &metadata.content_type,
+        content_encoding: metadata.content_encoding.as_deref(),
+        cache_control: metadata.cache_control.as_deref(),
+        metadata: &metadata.user_metadata,
+    })
+    .expect("object resource serializes");
+
+    let mut body = Vec::with_capacity(content.len() + object.len() + 256);
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{object}\r\n\
+             --{boundary}\r\nContent-Type: {}\r\n\r\n",
+            metadata.content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    (body, boundary)
+}
+
+impl GcsStorage {
+    /// Upload an object, with `if_absent` only if `key` does not exist yet
+    /// (`ifGenerationMatch=0`). Returns whether it was written.
+    async fn upload(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+        if_absent: bool,
+    ) -> Result<bool> {
+        let (body, boundary) = multipart_body(key, content, metadata);
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/upload/storage/v1/b/{}/o",
+                self.endpoint, self.bucket
+            ))
+            .query(&[("uploadType", "multipart")])
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/related; boundary={boundary}"),
+            )
+            .body(body);
+        if if_absent {
+            request = request.query(&[("ifGenerationMatch", "0")]);
+        }
+        if let Some(auth) = &self.auth {
+            let token = auth
+                .token(&[SCOPE])
+                .await
+                .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+            request = request.bearer_auth(token.as_str());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        let status = response.status();
+        if if_absent && status == reqwest::StatusCode::PRECONDITION_FAILED {
+            tracing::debug!("Not replacing existing gs://{}/{}", self.bucket, key);
+            return Ok(false);
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(HtmlSaverError::StorageUpload(
+                format!("GCS returned {status}: {message}").into(),
+            ));
+        }
+
+        tracing::debug!(
+            "Uploaded {} bytes to gs://{}/{}",
+            content.len(),
+            self.bucket,
+            key
+        );
+        Ok(true)
+    }
+}
+
+impl Storage for GcsStorage {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.upload(key, content, metadata, false).await.map(|_| ())
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        self.upload(key, content, metadata, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header_regex, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn put_sends_a_multipart_upload() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/upload/storage/v1/b/bucket/o"))
+            .and(query_param("uploadType", "multipart"))
+            .and(header_regex(
+                "content-type",
+                "^multipart/related; boundary=",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let storage = GcsStorage::emulator(server.uri(), "bucket");
+        let metadata = ObjectMetadata::new("text/html")
+            .cache_control("no-cache")
+            .user_metadata("source-url", "https://example.com/");
+        storage
+            .put("pages/index.html", b"<p>hi</p>", &metadata)
+            .await
+            .unwrap();
+
+        let request = &server.received_requests().await.unwrap()[0];
+        let body = String::from_utf8_lossy(&request.body);
+        assert!(body.contains(r#""name":"pages/index.html""#), "{body}");
+        assert!(body.contains(r#""cacheControl":"no-cache""#), "{body}");
+        assert!(
+            body.contains(r#""metadata":{"source-url":"https://example.com/"}"#),
+            "{body}"
+        );
+        assert!(body.contains("Content-Type: text/html\r\n\r\n<p>hi</p>\r\n"));
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_upload_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(403).set_body_string("forbidden"))
+            .mount(&server)
+            .await;
+
+        let storage = GcsStorage::emulator(server.uri(), "bucket");
+        let result = storage
+            .put(
+                "index.html",
+                b"<p>hi</p>",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(HtmlSaverError::StorageUpload(e)) if e.to_string().contains("403"))
+        );
+    }
+
+    #[test]
+    fn emulator_host_without_scheme_uses_http() {
+        let storage = GcsStorage::emulator("localhost:4443/", "bucket");
+        assert_eq!(storage.endpoint, "http://localhost:4443");
+    }
+
+    #[test]
+    fn invalid_service_account_is_a_config_error() {
+        let result = GcsStorage::from_service_account_json("{}", "bucket");
+        assert!(matches!(result, Err(HtmlSaverError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn put_if_absent_is_conditional_on_generation_zero() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(query_param("ifGenerationMatch", "0"))
+            .respond_with(ResponseTemplate::new(412))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let storage = GcsStorage::emulator(server.uri(), "bucket");
+        let written = storage
+            .put_if_absent(
+                "index.html",
+                b"<p>hi</p>",
+                &ObjectMetadata::new("text/html"),
+            )
+            .await
+            .unwrap();
+        assert!(!written);
+    }
+}
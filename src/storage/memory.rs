@@ -0,0 +1,230 @@
+//! In-memory storage backend for tests.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use futures::stream::{self, Stream};
+use tokio::sync::watch;
+
+use crate::error::Result;
+use crate::storage::{ObjectInfo, ObjectMetadata, Storage, StoredObject};
+
+/// Storage backend that keeps objects in memory, for testing code that saves
+/// through `html_saver` without touching the disk or the network.
+///
+/// Clones share the same objects, so a test can hand one clone to the
+/// builder and inspect another with [`get`](Self::get), [`keys`](Self::keys)
+/// and [`len`](Self::len), or wait for the worker with
+/// [`wait_for_len`](Self::wait_for_len). All [`Storage`] operations are
+/// supported, and [`put_if_absent`](Storage::put_if_absent) is atomic.
+///
+/// # Example
+///
+/// ```
+/// use html_saver::{HtmlSaverBuilder, MemoryStorage, Saveable};
+///
+/// struct Page;
+///
+/// impl Saveable for Page {
+///     fn content(&self) -> &str { "<p>hi</p>" }
+///     fn name(&self) -> String { "index.html".into() }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let storage = MemoryStorage::new();
+/// let handle = HtmlSaverBuilder::new(storage.clone())
+///     .batch_size(1)
+///     .build::<Page>();
+///
+/// handle.save(Page).unwrap();
+/// storage.wait_for_len(1).await;
+/// assert_eq!(storage.get("index.html").unwrap().content, b"<p>hi</p>");
+/// handle.shutdown().await;
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    objects: Mutex<BTreeMap<String, Entry>>,
+    /// Number of stored objects, for [`MemoryStorage::wait_for_len`].
+    len: watch::Sender<usize>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    object: StoredObject,
+    modified: SystemTime,
+}
+
+impl MemoryStorage {
+    /// Create an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The object stored under `key`, if any.
+    ///
+    /// This is a synchronous shortcut for [`Storage::get`], which it shadows
+    /// in method calls.
+    pub fn get(&self, key: &str) -> Option<StoredObject> {
+        self.objects().get(key).map(|entry| entry.object.clone())
+    }
+
+    /// The keys of all stored objects, in lexicographic order.
+    pub fn keys(&self) -> Vec<String> {
+        self.objects().keys().cloned().collect()
+    }
+
+    /// Number of stored objects.
+    pub fn len(&self) -> usize {
+        self.objects().len()
+    }
+
+    /// Whether no object is stored.
+    pub fn is_empty(&self) -> bool {
+        self.objects().is_empty()
+    }
+
+    /// Wait until at least `len` objects are stored.
+    ///
+    /// Wrap it in [`tokio::time::timeout`] to bound the wait.
+    pub async fn wait_for_len(&self, len: usize) {
+        let mut rx = self.shared.len.subscribe();
+        // The sender lives as long as `self`, so this cannot fail.
+        let _ = rx.wait_for(|&stored| stored >= len).await;
+    }
+
+    fn objects(&self) -> MutexGuard<'_, BTreeMap<String, Entry>> {
+        self.shared
+            .objects
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Store `content` under `key`, replacing an existing object only if
+    /// `replace` is set. Returns whether it was stored.
+    fn insert(&self, key: &str, content: &[u8], metadata: &ObjectMetadata, replace: bool) -> bool {
+        let mut objects = self.objects();
+        if !replace && objects.contains_key(key) {
+            return false;
+        }
+        let entry = Entry {
+            object: StoredObject::new(content.to_vec(), Some(metadata.clone())),
+            modified: SystemTime::now(),
+        };
+        objects.insert(key.to_string(), entry);
+        self.shared.len.send_replace(objects.len());
+        true
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.insert(key, content, metadata, true);
+        Ok(())
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        Ok(self.insert(key, content, metadata, false))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        Ok(MemoryStorage::get(self, key))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.objects().contains_key(key))
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> impl Stream<Item = Result<ObjectInfo>> + Send + 'a {
+        let found: Vec<_> = self
+            .objects()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, entry)| {
+                Ok(ObjectInfo::new(
+                    key.clone(),
+                    entry.object.content.len() as u64,
+                    Some(entry.modified),
+                ))
+            })
+            .collect();
+        stream::iter(found)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let mut objects = self.objects();
+        objects.remove(key);
+        self.shared.len.send_replace(objects.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    #[tokio::test]
+    async fn stores_lists_and_deletes() {
+        let storage = MemoryStorage::new();
+        storage.put("b/2.html", b"2", &html()).await.unwrap();
+        storage.put("a/1.html", b"1", &html()).await.unwrap();
+        storage.put("b/1.html", b"1", &html()).await.unwrap();
+        assert!(
+            !storage
+                .put_if_absent("b/1.html", b"x", &html())
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(storage.keys(), ["a/1.html", "b/1.html", "b/2.html"]);
+        assert_eq!(storage.get("b/1.html").unwrap().content, b"1");
+        let listed: Vec<String> = storage
+            .list("b/")
+            .map_ok(|info| info.key)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, ["b/1.html", "b/2.html"]);
+
+        storage.delete("b/1.html").await.unwrap();
+        assert!(!storage.exists("b/1.html").await.unwrap());
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn wait_for_len_resolves_once_enough_objects_are_stored() {
+        let storage = MemoryStorage::new();
+        let writer = storage.clone();
+        let waiter = tokio::spawn(async move { storage.wait_for_len(2).await });
+
+        writer.put("a.html", b"", &html()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        writer.put("b.html", b"", &html()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
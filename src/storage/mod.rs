@@ -1,14 +1,30 @@
 //! Pluggable storage backends for persisting HTML content.
 //!
-//! The crate ships with two built-in backends:
+//! The crate ships with these built-in backends:
 //!
 //! - [`FsStorage`] -- writes to the local filesystem.
 //! - [`S3Storage`] -- writes to an Amazon S3 (or compatible) bucket
 //!   (requires the `s3` feature).
+//! - [`GcsStorage`] -- writes to a Google Cloud Storage bucket (requires the
+//!   `gcs` feature).
+//! - [`AzureBlobStorage`] -- writes to an Azure Blob Storage container
+//!   (requires the `azure` feature).
+//! - [`ObjectStoreStorage`] -- writes through any [`object_store`] backend
+//!   (requires the `object-store` feature).
+//! - [`MemoryStorage`] -- keeps objects in memory, for tests.
 //!
-//! Implement the [`Storage`] trait to add your own backend.
+//! [`FaultyStorage`] wraps any of them to inject failures and latency in
+//! tests. Implement the [`Storage`] trait to add your own backend.
 
+#[cfg(feature = "azure")]
+mod azure;
+mod faulty;
 mod fs;
+#[cfg(feature = "gcs")]
+mod gcs;
+mod memory;
+#[cfg(feature = "object-store")]
+mod objstore;
 #[cfg(feature = "s3")]
 mod s3;
 
@@ -17,41 +33,262 @@ pub use aws_config::Region;
 #[cfg(feature = "s3")]
 pub use aws_sdk_s3::config::Credentials;
 #[cfg(feature = "s3")]
+pub use aws_sdk_s3::types::{
+    ChecksumAlgorithm, ObjectCannedAcl, ServerSideEncryption, StorageClass,
+};
+#[cfg(feature = "s3")]
 pub use aws_sdk_s3::{Client as S3Client, Config as S3Config, config::Builder as S3ConfigBuilder};
+#[cfg(feature = "azure")]
+pub use azure::AzureBlobStorage;
+pub use faulty::FaultyStorage;
 pub use fs::FsStorage;
+#[cfg(feature = "gcs")]
+pub use gcs::GcsStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "object-store")]
+pub use objstore::ObjectStoreStorage;
 #[cfg(feature = "s3")]
-pub use s3::S3Storage;
+pub use s3::{S3Storage, S3UploadOptions};
 
-use crate::error::Result;
+use crate::error::{HtmlSaverError, Result};
 
+use std::collections::BTreeMap;
 use std::future::Future;
+use std::time::SystemTime;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
 
 /// Trait for storage backends that can persist HTML content.
 ///
 /// Implementations must be `Send + Sync + 'static` so they can be used from
 /// the background worker task.
 ///
+/// Only [`put`](Self::put) is required. The read, list and delete operations
+/// are optional; their default implementations return
+/// [`HtmlSaverError::Unsupported`]. [`put_if_absent`](Self::put_if_absent)
+/// falls back to [`exists`](Self::exists) and [`put`](Self::put), so a backend
+/// that implements neither it, `exists` nor [`get`](Self::get) cannot be used
+/// with an [`OverwritePolicy`](crate::OverwritePolicy) other than `Overwrite`.
+///
 /// # Implementing a custom backend
 ///
 /// ```rust,no_run
-/// use html_saver::{Storage, Result};
+/// use html_saver::{ObjectMetadata, Storage, Result};
 ///
 /// struct MyStorage;
 ///
 /// impl Storage for MyStorage {
-///     async fn put(&self, key: &str, content: &[u8], content_type: &str) -> Result<()> {
+///     async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
 ///         // write content somewhere ...
 ///         Ok(())
 ///     }
 /// }
 /// ```
 pub trait Storage: Send + Sync + 'static {
-    /// Persist `content` under the given `key` with the specified MIME
-    /// `content_type` (typically `"text/html"`).
+    /// Persist `content` under the given `key`, described by `metadata`.
     fn put(
         &self,
         key: &str,
         content: &[u8],
-        content_type: &str,
+        metadata: &ObjectMetadata,
     ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Persist `content` under `key` only if no object is stored there yet,
+    /// returning whether it was written.
+    ///
+    /// Used by the [`OverwritePolicy`](crate::OverwritePolicy). The default
+    /// implementation checks [`exists`](Self::exists) before calling
+    /// [`put`](Self::put), which is not atomic; backends with conditional
+    /// writes override it. It returns
+    /// [`Unsupported("put_if_absent")`](HtmlSaverError::Unsupported) if
+    /// `exists` is unsupported.
+    fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> impl Future<Output = Result<bool>> + Send {
+        async move {
+            match self.exists(key).await {
+                Ok(true) => Ok(false),
+                Ok(false) => self.put(key, content, metadata).await.map(|()| true),
+                Err(HtmlSaverError::Unsupported(_)) => {
+                    Err(HtmlSaverError::Unsupported("put_if_absent"))
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    /// Read the object stored under `key`, or `None` if there is none.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<StoredObject>>> + Send {
+        let _ = key;
+        async { Err(HtmlSaverError::Unsupported("get")) }
+    }
+
+    /// Whether an object is stored under `key`.
+    ///
+    /// Defaults to [`get`](Self::get); backends should override it with a
+    /// cheaper check.
+    fn exists(&self, key: &str) -> impl Future<Output = Result<bool>> + Send {
+        let object = self.get(key);
+        async { Ok(object.await?.is_some()) }
+    }
+
+    /// Stream every object whose key starts with `prefix`.
+    ///
+    /// The order of the objects is backend-specific.
+    fn list<'a>(&'a self, prefix: &'a str) -> impl Stream<Item = Result<ObjectInfo>> + Send + 'a {
+        let _ = prefix;
+        stream::once(async { Err(HtmlSaverError::Unsupported("list")) })
+    }
+
+    /// Delete the object stored under `key`. Deleting a missing object is not
+    /// an error.
+    fn delete(&self, key: &str) -> impl Future<Output = Result<()>> + Send {
+        let _ = key;
+        async { Err(HtmlSaverError::Unsupported("delete")) }
+    }
+}
+
+/// An object read back with [`Storage::get`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StoredObject {
+    /// The stored bytes, exactly as written (still compressed if
+    /// [compression](crate::Compression) was enabled).
+    pub content: Vec<u8>,
+    /// The object's metadata, if the backend keeps it.
+    pub metadata: Option<ObjectMetadata>,
+}
+
+impl StoredObject {
+    /// An object with the given content and metadata.
+    pub fn new(content: Vec<u8>, metadata: Option<ObjectMetadata>) -> Self {
+        Self { content, metadata }
+    }
+}
+
+/// An entry yielded by [`Storage::list`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ObjectInfo {
+    /// Key of the object.
+    pub key: String,
+    /// Size of the stored object in bytes.
+    pub size: u64,
+    /// When the object was last written, if known.
+    pub last_modified: Option<SystemTime>,
+}
+
+impl ObjectInfo {
+    /// An entry for `key` of `size` bytes.
+    pub fn new(key: impl Into<String>, size: u64, last_modified: Option<SystemTime>) -> Self {
+        Self {
+            key: key.into(),
+            size,
+            last_modified,
+        }
+    }
+}
+
+/// Metadata stored alongside an object.
+///
+/// [`Saveable::metadata`](crate::Saveable::metadata) supplies it per item; the
+/// worker fills in [`content_encoding`](Self::content_encoding) when
+/// [compression](crate::Compression) is enabled. [`S3Storage`] maps it to the
+/// corresponding `PutObject` fields, [`FsStorage`] can write it to a sidecar
+/// JSON file.
+///
+/// # Example
+///
+/// ```
+/// use html_saver::ObjectMetadata;
+///
+/// let metadata = ObjectMetadata::new("text/html")
+///     .cache_control("max-age=3600")
+///     .user_metadata("source-url", "https://example.com/")
+///     .user_metadata("http-status", "200")
+///     .tag("crawl", "daily");
+/// assert_eq!(metadata.content_type, "text/html");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ObjectMetadata {
+    /// MIME type of the (uncompressed) content, e.g. `"text/html"`.
+    pub content_type: String,
+    /// Encoding applied to the content, e.g. `"gzip"`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// Value of the `Cache-Control` header, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    /// User-defined key/value metadata (`x-amz-meta-*` on S3).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user_metadata: BTreeMap<String, String>,
+    /// Object tags (S3 object tagging).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl ObjectMetadata {
+    /// Metadata for content of the given MIME type, without any encoding.
+    pub fn new(content_type: impl Into<String>) -> Self {
+        Self {
+            content_type: content_type.into(),
+            content_encoding: None,
+            cache_control: None,
+            user_metadata: BTreeMap::new(),
+            tags: BTreeMap::new(),
+        }
+    }
+
+    /// Set the `Content-Encoding` of the content.
+    pub fn content_encoding(mut self, encoding: impl Into<String>) -> Self {
+        self.content_encoding = Some(encoding.into());
+        self
+    }
+
+    /// Set the `Cache-Control` header.
+    pub fn cache_control(mut self, cache_control: impl Into<String>) -> Self {
+        self.cache_control = Some(cache_control.into());
+        self
+    }
+
+    /// Add a user-defined metadata entry, such as the source URL or the
+    /// scrape timestamp.
+    pub fn user_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.user_metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add an object tag.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Object-safe counterpart of [`Storage`], used where a backend of a different
+/// type than the primary one must be held (e.g. the dead-letter sink).
+pub(crate) trait DynStorage: Send + Sync + 'static {
+    fn put_dyn<'a>(
+        &'a self,
+        key: &'a str,
+        content: &'a [u8],
+        metadata: &'a ObjectMetadata,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+impl<S: Storage> DynStorage for S {
+    fn put_dyn<'a>(
+        &'a self,
+        key: &'a str,
+        content: &'a [u8],
+        metadata: &'a ObjectMetadata,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.put(key, content, metadata))
+    }
 }
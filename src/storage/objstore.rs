@@ -0,0 +1,414 @@
+//! Storage backend over the [`object_store`] crate (requires the
+//! `object-store` feature).
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use object_store::path::Path;
+use object_store::{
+    Attribute, Attributes, MultipartUpload, ObjectStore, PutMode, PutMultipartOptions, PutOptions,
+    PutPayload, TagSet,
+};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::storage::{ObjectMetadata, Storage};
+
+/// Storage backend that writes through any [`ObjectStore`]: Amazon S3,
+/// Google Cloud Storage, Azure Blob Storage, HTTP/WebDAV, the local
+/// filesystem or memory.
+///
+/// The [`ObjectMetadata`] of each item maps to object attributes
+/// (`Content-Type`, `Content-Encoding`, `Cache-Control` and user metadata)
+/// and tags, as far as the underlying store supports them. Stores without
+/// attribute support, such as the local filesystem, need
+/// [`write_metadata(false)`](ObjectStoreStorage::write_metadata).
+///
+/// Bodies of at least
+/// [`multipart_threshold`](ObjectStoreStorage::multipart_threshold) bytes are
+/// written as a multipart upload with up to
+/// [`part_concurrency`](ObjectStoreStorage::part_concurrency) parts in
+/// flight; a failed upload is aborted.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use html_saver::ObjectStoreStorage;
+///
+/// // From a URL, with options such as credentials:
+/// let storage = ObjectStoreStorage::from_url_opts(
+///     "s3://my-bucket/pages",
+///     [("aws_region", "eu-west-1")],
+/// )?;
+///
+/// // Sharing a store with other tooling:
+/// let store: Arc<dyn ObjectStore> = lake.object_store();
+/// let storage = ObjectStoreStorage::new(store);
+/// ```
+pub struct ObjectStoreStorage {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    write_metadata: bool,
+    multipart_threshold: usize,
+    part_size: usize,
+    part_concurrency: usize,
+}
+
+impl ObjectStoreStorage {
+    /// Wrap an existing store, e.g. one shared with other tooling.
+    ///
+    /// Defaults: multipart threshold of 64 MiB, 16 MiB parts, 4 parts in
+    /// flight.
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            store,
+            prefix: Path::default(),
+            write_metadata: true,
+            multipart_threshold: 64 * 1024 * 1024,
+            part_size: 16 * 1024 * 1024,
+            part_concurrency: 4,
+        }
+    }
+
+    /// Build a store from a URL such as `s3://bucket/prefix`,
+    /// `gs://bucket`, `az://container`, `https://host/path`,
+    /// `file:///var/data` or `memory:///`.
+    ///
+    /// Credentials and settings are read from the environment; the path of
+    /// the URL becomes a prefix of every key. Metadata is not written for
+    /// `file://` URLs.
+    pub fn from_url(url: &str) -> Result<Self> {
+        Self::from_url_opts(url, std::iter::empty::<(&str, &str)>())
+    }
+
+    /// Like [`from_url`](Self::from_url), with store options such as
+    /// `aws_access_key_id` or `google_service_account`.
+    pub fn from_url_opts<I, K, V>(url: &str, options: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        let url = url::Url::parse(url)
+            .map_err(|e| HtmlSaverError::Config(format!("object store URL: {e}")))?;
+        let (store, prefix) = object_store::parse_url_opts(&url, options)
+            .map_err(|e| HtmlSaverError::Config(format!("object store: {e}")))?;
+        Ok(Self::new(Arc::from(store))
+            .prefix(prefix)
+            .write_metadata(url.scheme() != "file"))
+    }
+
+    /// Prepend `prefix` to every key.
+    pub fn prefix(mut self, prefix: impl Into<Path>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Whether to send [`ObjectMetadata`] as object attributes and tags
+    /// (default: `true`).
+    pub fn write_metadata(mut self, enabled: bool) -> Self {
+        self.write_metadata = enabled;
+        self
+    }
+
+    /// The wrapped store.
+    pub fn inner(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    /// Use a multipart upload for bodies of at least `bytes` bytes.
+    pub fn multipart_threshold(mut self, bytes: usize) -> Self {
+        self.multipart_threshold = bytes;
+        self
+    }
+
+    /// Size of each part of a multipart upload.
+    ///
+    /// Some stores have a minimum part size, e.g. 5 MiB for S3.
+    pub fn part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(1);
+        self
+    }
+
+    /// Maximum number of parts of one multipart upload sent in parallel.
+    pub fn part_concurrency(mut self, parts: usize) -> Self {
+        self.part_concurrency = parts.max(1);
+        self
+    }
+
+    /// Location of `key` in the store.
+    fn path(&self, key: &str) -> Path {
+        Path::from_iter(self.prefix.parts().chain(Path::from(key).parts()))
+    }
+
+    async fn put_multipart(
+        &self,
+        path: &Path,
+        content: &[u8],
+        attributes: Attributes,
+        tags: TagSet,
+    ) -> Result<()> {
+        let opts = PutMultipartOptions {
+            tags,
+            attributes,
+            ..Default::default()
+        };
+        let mut upload = self
+            .store
+            .put_multipart_opts(path, opts)
+            .await
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+
+        let result = upload_parts(
+            upload.as_mut(),
+            content,
+            self.part_size,
+            self.part_concurrency,
+        )
+        .await;
+        if result.is_err()
+            && let Err(e) = upload.abort().await
+        {
+            tracing::warn!("Failed to abort multipart upload of {path}: {e}");
+        }
+        result.map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))
+    }
+}
+
+impl From<Arc<dyn ObjectStore>> for ObjectStoreStorage {
+    fn from(store: Arc<dyn ObjectStore>) -> Self {
+        Self::new(store)
+    }
+}
+
+/// Send `content` in parts of `part_size`, with at most `concurrency` in
+/// flight, and complete the upload.
+async fn upload_parts(
+    upload: &mut dyn MultipartUpload,
+    content: &[u8],
+    part_size: usize,
+    concurrency: usize,
+) -> object_store::Result<()> {
+    let mut chunks = content.chunks(part_size);
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        // Parts are copied only once a slot is free.
+        while in_flight.len() < concurrency {
+            match chunks.next() {
+                Some(chunk) => in_flight.push(upload.put_part(PutPayload::from(chunk.to_vec()))),
+                None => break,
+            }
+        }
+        match in_flight.next().await {
+            Some(part) => part?,
+            None => break,
+        }
+    }
+    upload.complete().await.map(|_| ())
+}
+
+/// Object attributes and tags for `metadata`.
+fn attributes(metadata: &ObjectMetadata) -> (Attributes, TagSet) {
+    let mut attributes = Attributes::new();
+    attributes.insert(Attribute::ContentType, metadata.content_type.clone().into());
+    if let Some(encoding) = &metadata.content_encoding {
+        attributes.insert(Attribute::ContentEncoding, encoding.clone().into());
+    }
+    if let Some(cache_control) = &metadata.cache_control {
+        attributes.insert(Attribute::CacheControl, cache_control.clone().into());
+    }
+    for (name, value) in &metadata.user_metadata {
+        attributes.insert(
+            Attribute::Metadata(name.clone().into()),
+            value.clone().into(),
+        );
+    }
+
+    let mut tags = TagSet::default();
+    for (key, value) in &metadata.tags {
+        tags.push(key, value);
+    }
+    (attributes, tags)
+}
+
+impl Storage for ObjectStoreStorage {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        let path = self.path(key);
+        let (attributes, tags) = if self.write_metadata {
+            attributes(metadata)
+        } else {
+            Default::default()
+        };
+        if content.len() >= self.multipart_threshold {
+            self.put_multipart(&path, content, attributes, tags).await?;
+        } else {
+            let opts = PutOptions {
+                tags,
+                attributes,
+                ..Default::default()
+            };
+            self.store
+                .put_opts(&path, PutPayload::from(content.to_vec()), opts)
+                .await
+                .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        }
+
+        tracing::debug!(
+            "Uploaded {} bytes to {} at {}",
+            content.len(),
+            self.store,
+            path
+        );
+        Ok(())
+    }
+
+    /// Uses [`PutMode::Create`] and a single request, whatever the size of
+    /// the body; stores without conditional writes return an error.
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        let path = self.path(key);
+        let (attributes, tags) = if self.write_metadata {
+            attributes(metadata)
+        } else {
+            Default::default()
+        };
+        let opts = PutOptions {
+            mode: PutMode::Create,
+            tags,
+            attributes,
+            ..Default::default()
+        };
+        match self
+            .store
+            .put_opts(&path, PutPayload::from(content.to_vec()), opts)
+            .await
+        {
+            Ok(_) => {
+                tracing::debug!(
+                    "Uploaded {} bytes to {} at {}",
+                    content.len(),
+                    self.store,
+                    path
+                );
+                Ok(true)
+            }
+            Err(object_store::Error::AlreadyExists { .. }) => Ok(false),
+            Err(e) => Err(HtmlSaverError::StorageUpload(Box::new(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    fn metadata() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+            .cache_control("no-cache")
+            .user_metadata("source-url", "https://example.com/")
+    }
+
+    async fn read(store: &Arc<dyn ObjectStore>, path: &str) -> (Vec<u8>, Attributes) {
+        let result = store.get(&Path::from(path)).await.unwrap();
+        let attributes = result.attributes.clone();
+        (result.bytes().await.unwrap().to_vec(), attributes)
+    }
+
+    #[tokio::test]
+    async fn put_writes_content_and_attributes() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let storage = ObjectStoreStorage::new(store.clone()).prefix("pages");
+        storage
+            .put("2026/index.html", b"<p>hi</p>", &metadata())
+            .await
+            .unwrap();
+
+        let (content, attributes) = read(&store, "pages/2026/index.html").await;
+        assert_eq!(content, b"<p>hi</p>");
+        assert_eq!(
+            attributes.get(&Attribute::ContentType).map(|v| v.as_ref()),
+            Some("text/html")
+        );
+        assert_eq!(
+            attributes
+                .get(&Attribute::Metadata("source-url".into()))
+                .map(|v| v.as_ref()),
+            Some("https://example.com/")
+        );
+    }
+
+    #[tokio::test]
+    async fn large_bodies_use_multipart_uploads() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let storage = ObjectStoreStorage::new(store.clone())
+            .multipart_threshold(8)
+            .part_size(3)
+            .part_concurrency(2);
+        storage
+            .put("index.html", b"0123456789", &metadata())
+            .await
+            .unwrap();
+
+        let (content, attributes) = read(&store, "index.html").await;
+        assert_eq!(content, b"0123456789");
+        assert_eq!(
+            attributes.get(&Attribute::CacheControl).map(|v| v.as_ref()),
+            Some("no-cache")
+        );
+    }
+
+    #[tokio::test]
+    async fn stores_can_be_built_from_urls() {
+        let storage = ObjectStoreStorage::from_url("memory:///archive").unwrap();
+        storage
+            .put("index.html", b"<p>hi</p>", &metadata())
+            .await
+            .unwrap();
+        let (content, _) = read(storage.inner(), "archive/index.html").await;
+        assert_eq!(content, b"<p>hi</p>");
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let url = format!("file://{}", tmp.path().display());
+        let storage = ObjectStoreStorage::from_url(&url).unwrap();
+        storage
+            .put("dir/index.html", b"<p>hi</p>", &metadata())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(tmp.path().join("dir/index.html")).unwrap(),
+            b"<p>hi</p>"
+        );
+
+        assert!(matches!(
+            ObjectStoreStorage::from_url("not a url"),
+            Err(HtmlSaverError::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_if_absent_uses_create_mode() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let storage = ObjectStoreStorage::new(store.clone());
+        assert!(
+            storage
+                .put_if_absent("index.html", b"first", &metadata())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .put_if_absent("index.html", b"second", &metadata())
+                .await
+                .unwrap()
+        );
+
+        let (content, _) = read(&store, "index.html").await;
+        assert_eq!(content, b"first");
+    }
+}
@@ -1,12 +1,39 @@
 //! Amazon S3 storage backend (requires the `s3` feature).
 
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
 use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{
+    ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart, ObjectCannedAcl,
+    ServerSideEncryption, StorageClass,
+};
+use futures::stream::{self, Stream};
+use futures::{StreamExt, TryStreamExt};
 
 use crate::error::{HtmlSaverError, Result};
-use crate::storage::Storage;
+use crate::storage::{ObjectInfo, ObjectMetadata, Storage, StoredObject};
 
 /// Storage backend that uploads files to an Amazon S3 (or S3-compatible) bucket.
 ///
+/// The [`ObjectMetadata`] of each item maps to the `Content-Type`,
+/// `Content-Encoding` and `Cache-Control` headers, user metadata
+/// (`x-amz-meta-*`) and object tags of the uploaded object.
+///
+/// Bodies of at least [`multipart_threshold`](S3Storage::multipart_threshold)
+/// bytes are sent as a multipart upload, with up to
+/// [`part_concurrency`](S3Storage::part_concurrency) parts in flight at once.
+/// If any part fails the upload is aborted, so no orphaned parts are left
+/// behind.
+///
+/// Encryption, storage class, ACL and checksums are set for every object with
+/// [`upload_options`](S3Storage::upload_options).
+///
+/// [`get`](Storage::get) returns the object's headers and user metadata but
+/// not its tags, which would take a separate request.
+///
 /// # Example
 ///
 /// ```rust,ignore
@@ -22,17 +49,156 @@ use crate::storage::Storage;
 pub struct S3Storage {
     client: Client,
     bucket: String,
+    multipart_threshold: usize,
+    part_size: usize,
+    part_concurrency: usize,
+    options: S3UploadOptions,
+}
+
+/// Settings applied to every object written by an [`S3Storage`].
+///
+/// All fields are unset by default, leaving the bucket's defaults in place.
+///
+/// ```rust,ignore
+/// use html_saver::{S3Storage, S3UploadOptions, StorageClass};
+///
+/// let storage = S3Storage::from_env("archive").await.upload_options(
+///     S3UploadOptions::new()
+///         .kms_key_id("arn:aws:kms:us-east-1:111122223333:key/1234abcd")
+///         .bucket_key_enabled(true)
+///         .storage_class(StorageClass::GlacierIr),
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct S3UploadOptions {
+    /// Server-side encryption mode (`x-amz-server-side-encryption`).
+    pub server_side_encryption: Option<ServerSideEncryption>,
+    /// KMS key used for `aws:kms` encryption.
+    pub kms_key_id: Option<String>,
+    /// Use an S3 Bucket Key for SSE-KMS, reducing KMS request costs.
+    pub bucket_key_enabled: Option<bool>,
+    /// Storage class, such as `STANDARD_IA` or `GLACIER_IR`.
+    pub storage_class: Option<StorageClass>,
+    /// Canned ACL.
+    pub acl: Option<ObjectCannedAcl>,
+    /// Account ID that must own the bucket, or the request fails.
+    pub expected_bucket_owner: Option<String>,
+    /// Algorithm of the additional checksum S3 verifies on upload.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+}
+
+impl S3UploadOptions {
+    /// Options that leave every setting to the bucket's defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the server-side encryption mode.
+    pub fn server_side_encryption(mut self, sse: ServerSideEncryption) -> Self {
+        self.server_side_encryption = Some(sse);
+        self
+    }
+
+    /// Encrypt with SSE-KMS using the given key ID or ARN.
+    ///
+    /// Also sets the encryption mode to `aws:kms` unless another KMS mode
+    /// (such as `aws:kms:dsse`) was chosen.
+    pub fn kms_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.kms_key_id = Some(key_id.into());
+        if !matches!(
+            self.server_side_encryption,
+            Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse)
+        ) {
+            self.server_side_encryption = Some(ServerSideEncryption::AwsKms);
+        }
+        self
+    }
+
+    /// Enable or disable the S3 Bucket Key for SSE-KMS.
+    pub fn bucket_key_enabled(mut self, enabled: bool) -> Self {
+        self.bucket_key_enabled = Some(enabled);
+        self
+    }
+
+    /// Set the storage class.
+    pub fn storage_class(mut self, class: StorageClass) -> Self {
+        self.storage_class = Some(class);
+        self
+    }
+
+    /// Set the canned ACL.
+    pub fn acl(mut self, acl: ObjectCannedAcl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    /// Require the bucket to be owned by the given account ID.
+    pub fn expected_bucket_owner(mut self, account_id: impl Into<String>) -> Self {
+        self.expected_bucket_owner = Some(account_id.into());
+        self
+    }
+
+    /// Have S3 verify an additional checksum of every upload.
+    pub fn checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum_algorithm = Some(algorithm);
+        self
+    }
 }
 
 impl S3Storage {
+    /// Smallest part S3 accepts for all but the last part of an upload.
+    pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+    /// Largest number of parts in a single multipart upload.
+    pub const MAX_PARTS: usize = 10_000;
+
     /// Create a new `S3Storage` with an existing [`Client`] and bucket name.
+    ///
+    /// Defaults: multipart threshold of 64 MiB, 16 MiB parts, 4 parts in
+    /// flight.
     pub fn new(client: Client, bucket: impl Into<String>) -> Self {
         Self {
             client,
             bucket: bucket.into(),
+            multipart_threshold: 64 * 1024 * 1024,
+            part_size: 16 * 1024 * 1024,
+            part_concurrency: 4,
+            options: S3UploadOptions::default(),
         }
     }
 
+    /// Apply encryption, storage class, ACL and checksum settings to every
+    /// object.
+    pub fn upload_options(mut self, options: S3UploadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Use a multipart upload for bodies of at least `bytes` bytes.
+    ///
+    /// Single `PutObject` requests are limited to 5 GiB by S3.
+    pub fn multipart_threshold(mut self, bytes: usize) -> Self {
+        self.multipart_threshold = bytes;
+        self
+    }
+
+    /// Size of each part of a multipart upload, at least
+    /// [`MIN_PART_SIZE`](Self::MIN_PART_SIZE).
+    ///
+    /// Grown as needed so a body never needs more than
+    /// [`MAX_PARTS`](Self::MAX_PARTS) parts.
+    pub fn part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(Self::MIN_PART_SIZE);
+        self
+    }
+
+    /// Maximum number of parts of one multipart upload sent in parallel.
+    pub fn part_concurrency(mut self, parts: usize) -> Self {
+        self.part_concurrency = parts.max(1);
+        self
+    }
+
     /// Create an `S3Storage` from an [`aws_sdk_s3::Config`].
     ///
     /// ```ignore
@@ -61,24 +227,773 @@ impl S3Storage {
     }
 }
 
+impl S3Storage {
+    /// Part size used for a body of `len` bytes.
+    fn part_size_for(&self, len: usize) -> usize {
+        self.part_size.max(len.div_ceil(Self::MAX_PARTS))
+    }
+
+    /// Upload `content` in parts. With `if_absent`, the upload is only
+    /// completed if `key` does not exist yet; returns whether it was.
+    async fn put_multipart(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+        if_absent: bool,
+    ) -> Result<bool> {
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(&metadata.content_type)
+            .set_content_encoding(metadata.content_encoding.clone())
+            .set_cache_control(metadata.cache_control.clone())
+            .set_metadata(user_metadata(metadata))
+            .set_tagging(tagging(metadata))
+            .set_server_side_encryption(self.options.server_side_encryption.clone())
+            .set_ssekms_key_id(self.options.kms_key_id.clone())
+            .set_bucket_key_enabled(self.options.bucket_key_enabled)
+            .set_storage_class(self.options.storage_class.clone())
+            .set_acl(self.options.acl.clone())
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .set_checksum_algorithm(self.options.checksum_algorithm.clone())
+            .send()
+            .await
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        let upload_id = upload.upload_id().ok_or_else(|| {
+            HtmlSaverError::StorageUpload("CreateMultipartUpload returned no upload id".into())
+        })?;
+
+        let result = match self.upload_parts(key, upload_id, content).await {
+            Ok(parts) => self
+                .client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+                .set_if_none_match(if_absent.then(|| "*".to_string()))
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .map(|_| true)
+                .or_else(|e| {
+                    if precondition_failed(&e) {
+                        Ok(false)
+                    } else {
+                        Err(HtmlSaverError::StorageUpload(Box::new(e)))
+                    }
+                }),
+            Err(e) => Err(e),
+        };
+        if !matches!(result, Ok(true)) {
+            self.abort(key, upload_id).await;
+        }
+        result
+    }
+
+    /// Upload `content` in parts, returning them in part-number order.
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        content: &[u8],
+    ) -> Result<Vec<CompletedPart>> {
+        let uploads: Vec<_> = content
+            .chunks(self.part_size_for(content.len()))
+            .zip(1..)
+            .map(|(chunk, part_number)| self.upload_part(key, upload_id, part_number, chunk))
+            .collect();
+        let mut parts: Vec<CompletedPart> = futures::stream::iter(uploads)
+            .buffer_unordered(self.part_concurrency)
+            .try_collect()
+            .await?;
+        parts.sort_by_key(|part| part.part_number);
+        Ok(parts)
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        chunk: &[u8],
+    ) -> Result<CompletedPart> {
+        let part = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .set_checksum_algorithm(self.options.checksum_algorithm.clone())
+            .body(ByteStream::from(chunk.to_vec()))
+            .send()
+            .await
+            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+        // Completing an upload created with a checksum algorithm requires
+        // each part's checksum.
+        Ok(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(part.e_tag)
+            .set_checksum_crc32(part.checksum_crc32)
+            .set_checksum_crc32_c(part.checksum_crc32_c)
+            .set_checksum_crc64_nvme(part.checksum_crc64_nvme)
+            .set_checksum_sha1(part.checksum_sha1)
+            .set_checksum_sha256(part.checksum_sha256)
+            .build())
+    }
+
+    /// Abort a failed multipart upload so S3 discards its parts.
+    async fn abort(&self, key: &str, upload_id: &str) {
+        let result = self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .send()
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to abort multipart upload of s3://{}/{}: {e}",
+                self.bucket,
+                key
+            );
+        }
+    }
+}
+
+impl S3Storage {
+    /// Upload an object, with `if_absent` only if `key` does not exist yet
+    /// (`If-None-Match: *`). Returns whether it was written.
+    async fn write(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+        if_absent: bool,
+    ) -> Result<bool> {
+        let written = if content.len() >= self.multipart_threshold {
+            self.put_multipart(key, content, metadata, if_absent)
+                .await?
+        } else {
+            let result = self
+                .client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .body(content.to_vec().into())
+                .content_type(&metadata.content_type)
+                .set_content_encoding(metadata.content_encoding.clone())
+                .set_cache_control(metadata.cache_control.clone())
+                .set_metadata(user_metadata(metadata))
+                .set_tagging(tagging(metadata))
+                .set_server_side_encryption(self.options.server_side_encryption.clone())
+                .set_ssekms_key_id(self.options.kms_key_id.clone())
+                .set_bucket_key_enabled(self.options.bucket_key_enabled)
+                .set_storage_class(self.options.storage_class.clone())
+                .set_acl(self.options.acl.clone())
+                .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+                .set_checksum_algorithm(self.options.checksum_algorithm.clone())
+                .set_if_none_match(if_absent.then(|| "*".to_string()))
+                .send()
+                .await;
+            match result {
+                Ok(_) => true,
+                Err(e) if precondition_failed(&e) => false,
+                Err(e) => return Err(HtmlSaverError::StorageUpload(Box::new(e))),
+            }
+        };
+
+        if written {
+            tracing::debug!(
+                "Uploaded {} bytes to s3://{}/{}",
+                content.len(),
+                self.bucket,
+                key
+            );
+        } else {
+            tracing::debug!("Not replacing existing s3://{}/{}", self.bucket, key);
+        }
+        Ok(written)
+    }
+}
+
 impl Storage for S3Storage {
-    async fn put(&self, key: &str, content: &[u8], content_type: &str) -> Result<()> {
+    async fn put(&self, key: &str, content: &[u8], metadata: &ObjectMetadata) -> Result<()> {
+        self.write(key, content, metadata, false).await.map(|_| ())
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> Result<bool> {
+        self.write(key, content, metadata, true).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                return Ok(None);
+            }
+            Err(e) => return Err(HtmlSaverError::Storage(Box::new(e))),
+        };
+
+        let mut metadata =
+            ObjectMetadata::new(output.content_type().unwrap_or("binary/octet-stream"));
+        metadata.content_encoding = output.content_encoding().map(str::to_string);
+        metadata.cache_control = output.cache_control().map(str::to_string);
+        metadata.user_metadata = output
+            .metadata()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        let content = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| HtmlSaverError::Storage(Box::new(e)))?
+            .to_vec();
+        Ok(Some(StoredObject::new(content, Some(metadata))))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(e) => Err(HtmlSaverError::Storage(Box::new(e))),
+        }
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> impl Stream<Item = Result<ObjectInfo>> + Send + 'a {
+        let pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
+            .into_paginator()
+            .send();
+        stream::unfold(pages, |mut pages| async move {
+            let page = pages.next().await?;
+            Some((page, pages))
+        })
+        .flat_map(|page| {
+            let objects = match page {
+                Ok(page) => page
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|object| Ok(object_info(object)))
+                    .collect(),
+                Err(e) => vec![Err(HtmlSaverError::Storage(Box::new(e)))],
+            };
+            stream::iter(objects)
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
         self.client
-            .put_object()
+            .delete_object()
             .bucket(&self.bucket)
             .key(key)
-            .body(content.to_vec().into())
-            .content_type(content_type)
+            .set_expected_bucket_owner(self.options.expected_bucket_owner.clone())
             .send()
             .await
-            .map_err(|e| HtmlSaverError::StorageUpload(Box::new(e)))?;
+            .map_err(|e| HtmlSaverError::Storage(Box::new(e)))?;
+        tracing::debug!("Deleted s3://{}/{}", self.bucket, key);
+        Ok(())
+    }
+}
+
+/// Whether a conditional write failed because the key already exists.
+fn precondition_failed<E>(e: &SdkError<E>) -> bool {
+    e.raw_response()
+        .is_some_and(|response| response.status().as_u16() == 412)
+}
+
+/// Listing entry for an object returned by `ListObjectsV2`.
+fn object_info(object: aws_sdk_s3::types::Object) -> ObjectInfo {
+    ObjectInfo::new(
+        object.key.unwrap_or_default(),
+        object.size.unwrap_or_default().max(0) as u64,
+        object
+            .last_modified
+            .and_then(|t| SystemTime::try_from(t).ok()),
+    )
+}
+
+/// User metadata as sent in `x-amz-meta-*` headers.
+fn user_metadata(metadata: &ObjectMetadata) -> Option<HashMap<String, String>> {
+    (!metadata.user_metadata.is_empty()).then(|| {
+        metadata
+            .user_metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    })
+}
+
+/// Object tags as sent in the `x-amz-tagging` header.
+fn tagging(metadata: &ObjectMetadata) -> Option<String> {
+    (!metadata.tags.is_empty()).then(|| encode_tagging(&metadata.tags))
+}
+
+/// Encode object tags as the URL query string expected by `x-amz-tagging`.
+fn encode_tagging(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use aws_sdk_s3::config::retry::RetryConfig;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+
+    use super::*;
+
+    const MIB: usize = 1024 * 1024;
+
+    /// A request seen by [`MockS3`].
+    #[derive(Clone, Debug)]
+    struct Request {
+        method: String,
+        path: String,
+        query: String,
+        headers: http::HeaderMap,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn is_part(&self) -> bool {
+            self.method == "PUT" && self.query.contains("partNumber=")
+        }
+
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.get(name).and_then(|v| v.to_str().ok())
+        }
+    }
 
-        tracing::debug!(
-            "Uploaded {} bytes to s3://{}/{}",
-            content.len(),
-            self.bucket,
-            key
+    /// Answers S3 requests in memory, optionally failing one part.
+    #[derive(Clone, Default)]
+    struct MockS3 {
+        requests: Arc<Mutex<Vec<Request>>>,
+        fail_part: Option<i32>,
+    }
+
+    impl MockS3 {
+        fn storage(&self) -> S3Storage {
+            let mock = self.clone();
+            let config = aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-east-1"))
+                .credentials_provider(Credentials::new("AKID", "SECRET", None, None, "test"))
+                .force_path_style(true)
+                .retry_config(RetryConfig::disabled())
+                .http_client(infallible_client_fn(move |req| mock.handle(req)))
+                .build();
+            S3Storage::from_conf(config, "bucket")
+                .multipart_threshold(8 * MIB)
+                .part_size(5 * MIB)
+        }
+
+        fn handle(&self, req: http::Request<SdkBody>) -> http::Response<SdkBody> {
+            let request = Request {
+                method: req.method().to_string(),
+                path: req.uri().path().to_string(),
+                query: req.uri().query().unwrap_or_default().to_string(),
+                headers: req.headers().clone(),
+                body: req.body().bytes().unwrap_or_default().to_vec(),
+            };
+            self.requests.lock().unwrap().push(request.clone());
+
+            let mut response = http::Response::builder();
+            // Echo checksums back, as S3 does.
+            for (name, value) in &request.headers {
+                if name.as_str().starts_with("x-amz-checksum-") {
+                    response = response.header(name, value);
+                }
+            }
+            let part_number = request
+                .query
+                .split('&')
+                .find_map(|p| p.strip_prefix("partNumber="))
+                .and_then(|n| n.parse::<i32>().ok());
+            let missing = request.path.ends_with("/missing.html");
+            // `existing.html` fails writes conditional on its absence.
+            let conflict = request.path.ends_with("/existing.html")
+                && request.header("if-none-match") == Some("*");
+            match (request.method.as_str(), part_number) {
+                ("PUT" | "POST", _) if conflict => response.status(412).body(SdkBody::from(
+                    "<Error><Code>PreconditionFailed</Code><Message>exists</Message></Error>",
+                )),
+                ("GET", _) if request.query.contains("list-type=2") => {
+                    // Two pages, to exercise pagination.
+                    let (contents, next) = if request.query.contains("continuation-token=") {
+                        ("<Key>pages/b.html</Key><Size>20</Size>", "")
+                    } else {
+                        (
+                            "<Key>pages/a.html</Key><Size>10</Size>\
+                             <LastModified>2026-01-02T03:04:05.000Z</LastModified>",
+                            "<IsTruncated>true</IsTruncated>\
+                             <NextContinuationToken>page-2</NextContinuationToken>",
+                        )
+                    };
+                    response.status(200).body(SdkBody::from(format!(
+                        "<ListBucketResult><Name>bucket</Name>{next}\
+                         <Contents>{contents}</Contents></ListBucketResult>"
+                    )))
+                }
+                ("GET", _) if missing => response.status(404).body(SdkBody::from(
+                    "<Error><Code>NoSuchKey</Code><Message>missing</Message></Error>",
+                )),
+                ("GET", _) => response
+                    .status(200)
+                    .header("Content-Type", "text/html")
+                    .header("Cache-Control", "no-cache")
+                    .header("x-amz-meta-source-url", "https://example.com/")
+                    .body(SdkBody::from("<p>hi</p>")),
+                ("HEAD", _) if missing => response.status(404).body(SdkBody::empty()),
+                ("PUT", Some(n)) if Some(n) == self.fail_part => {
+                    response.status(403).body(SdkBody::from(
+                        "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+                    ))
+                }
+                ("PUT", Some(n)) => response
+                    .status(200)
+                    .header("ETag", format!("\"etag-{n}\""))
+                    .body(SdkBody::empty()),
+                ("POST", _) if request.query.contains("uploads") => {
+                    response.status(200).body(SdkBody::from(
+                        "<InitiateMultipartUploadResult><Bucket>bucket</Bucket>\
+                         <Key>key</Key><UploadId>upload-1</UploadId>\
+                         </InitiateMultipartUploadResult>",
+                    ))
+                }
+                ("POST", _) => response.status(200).body(SdkBody::from(
+                    "<CompleteMultipartUploadResult><Bucket>bucket</Bucket>\
+                     <Key>key</Key><ETag>\"etag\"</ETag></CompleteMultipartUploadResult>",
+                )),
+                ("DELETE", _) => response.status(204).body(SdkBody::empty()),
+                _ => response.status(200).body(SdkBody::empty()),
+            }
+            .unwrap()
+        }
+
+        fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    fn archive_options() -> S3UploadOptions {
+        S3UploadOptions::new()
+            .kms_key_id("key-1")
+            .bucket_key_enabled(true)
+            .storage_class(StorageClass::GlacierIr)
+            .acl(ObjectCannedAcl::BucketOwnerFullControl)
+            .expected_bucket_owner("111122223333")
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+    }
+
+    #[tokio::test]
+    async fn small_bodies_use_a_single_put() {
+        let mock = MockS3::default();
+        mock.storage()
+            .put("page.html", b"<p>hi</p>", &html())
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].body, b"<p>hi</p>");
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_uploaded_in_parts() {
+        let mock = MockS3::default();
+        let content: Vec<u8> = (0..12 * MIB).map(|i| i as u8).collect();
+        mock.storage()
+            .put("page.html", &content, &html())
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        let parts: Vec<_> = requests.iter().filter(|r| r.is_part()).collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts.iter().map(|r| r.body.len()).sum::<usize>(),
+            content.len()
         );
-        Ok(())
+
+        let complete = String::from_utf8_lossy(&requests.last().unwrap().body).into_owned();
+        let order: Vec<_> = ["etag-1", "etag-2", "etag-3"]
+            .iter()
+            .map(|etag| complete.find(etag).unwrap())
+            .collect();
+        assert!(order.is_sorted(), "parts out of order: {complete}");
+    }
+
+    #[tokio::test]
+    async fn failed_parts_abort_the_upload() {
+        let mock = MockS3 {
+            fail_part: Some(2),
+            ..Default::default()
+        };
+        let content = vec![b'x'; 12 * MIB];
+        let result = mock.storage().put("page.html", &content, &html()).await;
+        assert!(matches!(result, Err(HtmlSaverError::StorageUpload(_))));
+
+        let requests = mock.requests();
+        let last = requests.last().unwrap();
+        assert_eq!(last.method, "DELETE");
+        assert!(last.query.contains("uploadId=upload-1"));
+        assert!(
+            !requests
+                .iter()
+                .any(|r| r.method == "POST" && r.query.contains("uploadId"))
+        );
+    }
+
+    #[test]
+    fn part_size_grows_to_stay_within_the_part_limit() {
+        let mock = MockS3::default();
+        let storage = mock.storage();
+        assert_eq!(storage.part_size_for(12 * MIB), 5 * MIB);
+        let huge = 100_000 * MIB;
+        assert!(storage.part_size_for(huge) * S3Storage::MAX_PARTS >= huge);
+    }
+
+    #[test]
+    fn kms_key_implies_kms_encryption() {
+        let options = S3UploadOptions::new().kms_key_id("key-1");
+        assert_eq!(
+            options.server_side_encryption,
+            Some(ServerSideEncryption::AwsKms)
+        );
+        let options = S3UploadOptions::new()
+            .server_side_encryption(ServerSideEncryption::AwsKmsDsse)
+            .kms_key_id("key-1");
+        assert_eq!(
+            options.server_side_encryption,
+            Some(ServerSideEncryption::AwsKmsDsse)
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_options_are_sent_with_every_put() {
+        let mock = MockS3::default();
+        mock.storage()
+            .upload_options(archive_options())
+            .put("page.html", b"<p>hi</p>", &html())
+            .await
+            .unwrap();
+
+        let put = &mock.requests()[0];
+        assert_eq!(put.header("x-amz-server-side-encryption"), Some("aws:kms"));
+        assert_eq!(
+            put.header("x-amz-server-side-encryption-aws-kms-key-id"),
+            Some("key-1")
+        );
+        assert_eq!(
+            put.header("x-amz-server-side-encryption-bucket-key-enabled"),
+            Some("true")
+        );
+        assert_eq!(put.header("x-amz-storage-class"), Some("GLACIER_IR"));
+        assert_eq!(put.header("x-amz-acl"), Some("bucket-owner-full-control"));
+        assert_eq!(
+            put.header("x-amz-expected-bucket-owner"),
+            Some("111122223333")
+        );
+        assert!(put.header("x-amz-checksum-sha256").is_some());
+    }
+
+    #[tokio::test]
+    async fn upload_options_apply_to_multipart_uploads() {
+        let mock = MockS3::default();
+        let content = vec![b'x'; 12 * MIB];
+        mock.storage()
+            .upload_options(archive_options())
+            .put("page.html", &content, &html())
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        let create = &requests[0];
+        assert_eq!(create.header("x-amz-storage-class"), Some("GLACIER_IR"));
+        assert_eq!(
+            create.header("x-amz-server-side-encryption-aws-kms-key-id"),
+            Some("key-1")
+        );
+        assert_eq!(create.header("x-amz-checksum-algorithm"), Some("SHA256"));
+        for request in &requests {
+            assert_eq!(
+                request.header("x-amz-expected-bucket-owner"),
+                Some("111122223333"),
+                "{} {}",
+                request.method,
+                request.query
+            );
+        }
+        for part in requests.iter().filter(|r| r.is_part()) {
+            assert!(part.header("x-amz-checksum-sha256").is_some());
+        }
+        let complete = String::from_utf8_lossy(&requests.last().unwrap().body).into_owned();
+        assert_eq!(complete.matches("<ChecksumSHA256>").count(), 3);
+    }
+
+    #[test]
+    fn tagging_is_url_encoded() {
+        let tags = BTreeMap::from([
+            ("crawl".to_string(), "daily run".to_string()),
+            ("source".to_string(), "a&b=c".to_string()),
+        ]);
+        assert_eq!(encode_tagging(&tags), "crawl=daily%20run&source=a%26b%3Dc");
+    }
+
+    #[tokio::test]
+    async fn get_returns_content_and_metadata() {
+        let mock = MockS3::default();
+        let storage = mock.storage();
+
+        let object = storage.get("page.html").await.unwrap().unwrap();
+        assert_eq!(object.content, b"<p>hi</p>");
+        let metadata = object.metadata.unwrap();
+        assert_eq!(metadata.content_type, "text/html");
+        assert_eq!(metadata.cache_control.as_deref(), Some("no-cache"));
+        assert_eq!(
+            metadata.user_metadata.get("source-url").map(String::as_str),
+            Some("https://example.com/")
+        );
+
+        assert!(storage.get("missing.html").await.unwrap().is_none());
+        assert!(storage.exists("page.html").await.unwrap());
+        assert!(!storage.exists("missing.html").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn list_follows_continuation_tokens() {
+        let mock = MockS3::default();
+        let objects: Vec<ObjectInfo> = mock.storage().list("pages/").try_collect().await.unwrap();
+
+        assert_eq!(
+            objects.iter().map(|o| o.key.as_str()).collect::<Vec<_>>(),
+            ["pages/a.html", "pages/b.html"]
+        );
+        assert_eq!(objects[0].size, 10);
+        assert!(objects[0].last_modified.is_some());
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].query.contains("prefix=pages%2F"));
+    }
+
+    #[tokio::test]
+    async fn delete_sends_delete_object() {
+        let mock = MockS3::default();
+        mock.storage().delete("page.html").await.unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].method, "DELETE");
+        assert_eq!(requests[0].path, "/bucket/page.html");
+    }
+
+    #[tokio::test]
+    async fn put_if_absent_sends_if_none_match() {
+        let mock = MockS3::default();
+        let storage = mock.storage();
+
+        assert!(
+            storage
+                .put_if_absent("new.html", b"<p>hi</p>", &html())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !storage
+                .put_if_absent("existing.html", b"<p>hi</p>", &html())
+                .await
+                .unwrap()
+        );
+        storage
+            .put("existing.html", b"<p>hi</p>", &html())
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].header("if-none-match"), Some("*"));
+        assert_eq!(requests[1].header("if-none-match"), Some("*"));
+        assert_eq!(requests[2].header("if-none-match"), None);
+    }
+
+    #[tokio::test]
+    async fn conditional_multipart_uploads_are_aborted_if_the_key_exists() {
+        let mock = MockS3::default();
+        let body = vec![0u8; 11 * MIB];
+        let written = mock
+            .storage()
+            .put_if_absent("existing.html", &body, &html())
+            .await
+            .unwrap();
+        assert!(!written);
+
+        let requests = mock.requests();
+        let complete = requests
+            .iter()
+            .find(|r| r.method == "POST" && !r.query.contains("uploads"))
+            .unwrap();
+        assert_eq!(complete.header("if-none-match"), Some("*"));
+        assert_eq!(requests.last().unwrap().method, "DELETE");
     }
 }
@@ -0,0 +1,457 @@
+//! Templates for building storage keys from an item's fields.
+//!
+//! Set with [`HtmlSaverBuilder::key_template`](crate::HtmlSaverBuilder::key_template).
+//! A template such as `{date:%Y/%m/%d}/{host}/{hour}/{name}` replaces
+//! [`Saveable::name`] as the key of every item, so documents can be
+//! partitioned by time and domain. The builder's prefix, if any, is still
+//! prepended.
+//!
+//! | Placeholder | Value |
+//! |-------------|-------|
+//! | `{name}` | [`Saveable::name`] |
+//! | `{date}` | UTC date as `2026-01-15` |
+//! | `{date:FORMAT}` | UTC date and time formatted with `%Y`, `%y`, `%m`, `%d`, `%j`, `%H`, `%M`, `%S` and `%%` |
+//! | `{year}`, `{month}`, `{day}`, `{hour}`, `{minute}`, `{second}` | Zero-padded parts of the UTC time |
+//! | `{timestamp}` | Seconds since the Unix epoch |
+//! | `{sha256}`, `{sha256:N}` | SHA-256 of [`Saveable::content_bytes`], in hex, or its first `N` hex digits |
+//! | `{anything_else}` | [`Saveable::key_field`] |
+//!
+//! `{{` and `}}` stand for literal braces.
+//!
+//! The template is rendered when the item is saved: the time is the time of
+//! the `save` call, and the rendered key is what the
+//! [write-ahead log](crate::wal) records, so replayed items keep their key.
+//! The [`KeyPolicy`](crate::KeyPolicy) then applies to the rendered key.
+
+use std::fmt::Write as _;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{HtmlSaverError, Result};
+use crate::key::validate_key;
+use crate::saveable::Saveable;
+
+/// A parsed key template. See the [module documentation](self) for the
+/// syntax.
+///
+/// ```
+/// use html_saver::template::KeyTemplate;
+///
+/// assert!(KeyTemplate::parse("{date:%Y/%m/%d}/{host}/{name}").is_ok());
+/// assert!(KeyTemplate::parse("{date:%Q}/{name}").is_err());
+/// assert!(KeyTemplate::parse("{name").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Name,
+    Date(Vec<DatePart>),
+    Timestamp,
+    Sha256(usize),
+    Field(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum DatePart {
+    Literal(String),
+    Year,
+    ShortYear,
+    Month,
+    Day,
+    DayOfYear,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl KeyTemplate {
+    /// Parse a template, failing with [`HtmlSaverError::Config`] on unclosed
+    /// or empty placeholders, unknown date specifiers, hash lengths outside
+    /// `1..=64` and templates that cannot produce a valid key (for instance
+    /// one starting with `/`).
+    pub fn parse(template: &str) -> Result<Self> {
+        let config = |msg: String| Err(HtmlSaverError::Config(format!("key template: {msg}")));
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return config(format!("unmatched `}}` in {template:?}")),
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return config(format!("unclosed `{{` in {template:?}"));
+                    };
+                    let placeholder = &rest[..end];
+                    chars = rest[end + 1..].chars();
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(match parse_placeholder(placeholder) {
+                        Ok(part) => part,
+                        Err(msg) => return config(msg),
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        let parsed = Self {
+            source: template.to_string(),
+            parts,
+        };
+        // Every value is at least one plain character, so a sample key that
+        // is invalid means the template itself is.
+        let sample = parsed.render_with(UNIX_EPOCH, |_| Ok("x".to_string()), || "x".into(), &[])?;
+        if let Err(e) = validate_key(&sample) {
+            return config(e.to_string());
+        }
+        Ok(parsed)
+    }
+
+    /// The template as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the key of `item` as of `time`.
+    ///
+    /// Fails with [`HtmlSaverError::InvalidKey`] if the template uses a field
+    /// that [`Saveable::key_field`] has no value for. The rendered key is not
+    /// validated here; the worker applies the [`KeyPolicy`](crate::KeyPolicy).
+    pub fn render<R: Saveable + ?Sized>(&self, item: &R, time: SystemTime) -> Result<String> {
+        self.render_with(
+            time,
+            |field| {
+                item.key_field(field)
+                    .ok_or_else(|| HtmlSaverError::InvalidKey {
+                        key: format!("{{{field}}}"),
+                        reason: "template field has no value",
+                    })
+            },
+            || item.name(),
+            item.content_bytes(),
+        )
+    }
+
+    fn render_with(
+        &self,
+        time: SystemTime,
+        field: impl Fn(&str) -> Result<String>,
+        name: impl Fn() -> String,
+        content: &[u8],
+    ) -> Result<String> {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let utc = Utc::from_unix(secs);
+        let mut name_value = None;
+        let mut hash = None;
+
+        let mut key = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => key.push_str(s),
+                Part::Name => key.push_str(name_value.get_or_insert_with(&name)),
+                Part::Date(format) => utc.format(format, &mut key),
+                Part::Timestamp => {
+                    let _ = write!(key, "{secs}");
+                }
+                Part::Sha256(len) => {
+                    let hash = hash.get_or_insert_with(|| hex_sha256(content));
+                    key.push_str(&hash[..*len]);
+                }
+                Part::Field(field_name) => key.push_str(&field(field_name)?),
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl FromStr for KeyTemplate {
+    type Err = HtmlSaverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> std::result::Result<Part, String> {
+    let (field, arg) = match placeholder.split_once(':') {
+        Some((field, arg)) => (field, Some(arg)),
+        None => (placeholder, None),
+    };
+    let fixed = |spec: DatePart| Part::Date(vec![spec]);
+    let part = match (field, arg) {
+        ("", _) => return Err("empty placeholder `{}`".to_string()),
+        ("name", None) => Part::Name,
+        ("date", None) => Part::Date(parse_date_format("%Y-%m-%d")?),
+        ("date", Some(format)) => Part::Date(parse_date_format(format)?),
+        ("year", None) => fixed(DatePart::Year),
+        ("month", None) => fixed(DatePart::Month),
+        ("day", None) => fixed(DatePart::Day),
+        ("hour", None) => fixed(DatePart::Hour),
+        ("minute", None) => fixed(DatePart::Minute),
+        ("second", None) => fixed(DatePart::Second),
+        ("timestamp", None) => Part::Timestamp,
+        ("sha256", None) => Part::Sha256(64),
+        ("sha256", Some(len)) => match len.parse() {
+            Ok(len @ 1..=64) => Part::Sha256(len),
+            _ => return Err(format!("`{{sha256:{len}}}` needs a length from 1 to 64")),
+        },
+        (field, None) if is_field_name(field) => Part::Field(field.to_string()),
+        _ => return Err(format!("unknown placeholder `{{{placeholder}}}`")),
+    };
+    Ok(part)
+}
+
+fn parse_date_format(format: &str) -> std::result::Result<Vec<DatePart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        let spec = match chars.next() {
+            Some('%') => {
+                literal.push('%');
+                continue;
+            }
+            Some('Y') => DatePart::Year,
+            Some('y') => DatePart::ShortYear,
+            Some('m') => DatePart::Month,
+            Some('d') => DatePart::Day,
+            Some('j') => DatePart::DayOfYear,
+            Some('H') => DatePart::Hour,
+            Some('M') => DatePart::Minute,
+            Some('S') => DatePart::Second,
+            Some(c) => return Err(format!("unknown date specifier `%{c}` in {format:?}")),
+            None => return Err(format!("trailing `%` in date format {format:?}")),
+        };
+        if !literal.is_empty() {
+            parts.push(DatePart::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(spec);
+    }
+    if !literal.is_empty() {
+        parts.push(DatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Field names are ASCII letters, digits, `_` and `-`.
+fn is_field_name(field: &str) -> bool {
+    field
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Hex SHA-256 of `content`.
+pub(crate) fn hex_sha256(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// A UTC date and time broken into its calendar fields.
+#[derive(Debug, PartialEq, Eq)]
+struct Utc {
+    year: i64,
+    month: u32,
+    day: u32,
+    day_of_year: u32,
+    hour: u64,
+    minute: u64,
+    second: u64,
+}
+
+impl Utc {
+    fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86_400) as i64;
+        let rem = secs % 86_400;
+
+        // Howard Hinnant's `civil_from_days`, for days since 1970-01-01.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let before_month =
+            [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334][month as usize - 1];
+        let day_of_year = before_month + day + u32::from(leap && month > 2);
+
+        Self {
+            year,
+            month,
+            day,
+            day_of_year,
+            hour: rem / 3600,
+            minute: rem % 3600 / 60,
+            second: rem % 60,
+        }
+    }
+
+    fn format(&self, format: &[DatePart], out: &mut String) {
+        for part in format {
+            let _ = match part {
+                DatePart::Literal(s) => {
+                    out.push_str(s);
+                    Ok(())
+                }
+                DatePart::Year => write!(out, "{:04}", self.year),
+                DatePart::ShortYear => write!(out, "{:02}", self.year % 100),
+                DatePart::Month => write!(out, "{:02}", self.month),
+                DatePart::Day => write!(out, "{:02}", self.day),
+                DatePart::DayOfYear => write!(out, "{:03}", self.day_of_year),
+                DatePart::Hour => write!(out, "{:02}", self.hour),
+                DatePart::Minute => write!(out, "{:02}", self.minute),
+                DatePart::Second => write!(out, "{:02}", self.second),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    struct Page {
+        host: Option<&'static str>,
+    }
+
+    impl Saveable for Page {
+        fn content(&self) -> &str {
+            "<p>hi</p>"
+        }
+
+        fn name(&self) -> String {
+            "index.html".into()
+        }
+
+        fn key_field(&self, field: &str) -> Option<String> {
+            match field {
+                "host" => self.host.map(String::from),
+                _ => None,
+            }
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn render(template: &str) -> String {
+        KeyTemplate::parse(template)
+            .unwrap()
+            .render(
+                &Page {
+                    host: Some("example.com"),
+                },
+                at(1_768_480_245),
+            )
+            .unwrap()
+    }
+
+    fn config_error(template: &str) -> String {
+        match KeyTemplate::parse(template) {
+            Err(HtmlSaverError::Config(msg)) => msg,
+            other => panic!("expected Config error for {template:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn renders_dates_fields_and_names() {
+        assert_eq!(
+            render("{date:%Y/%m/%d}/{host}/{hour}/{name}"),
+            "2026/01/15/example.com/12/index.html"
+        );
+        assert_eq!(render("dt={date}/{name}"), "dt=2026-01-15/index.html");
+        assert_eq!(
+            render("{year}-{month}-{day}T{hour}:{minute}:{second}/{timestamp}"),
+            "2026-01-15T12:30:45/1768480245"
+        );
+        assert_eq!(render("{date:%y%j %%}/{{x}}"), "26015 %/{x}");
+    }
+
+    #[test]
+    fn hashes_the_content() {
+        assert_eq!(render("{sha256:8}.html"), "0a473528.html");
+        assert_eq!(
+            render("{sha256}"),
+            "0a4735281db700223af63abc387c351f64ea6961a1ef955631df08d96169e772"
+        );
+    }
+
+    #[test]
+    fn missing_fields_fail_the_item() {
+        let template = KeyTemplate::parse("{host}/{name}").unwrap();
+        let err = template.render(&Page { host: None }, at(0)).unwrap_err();
+        assert!(matches!(
+            err,
+            HtmlSaverError::InvalidKey { key, reason: "template field has no value" } if key == "{host}"
+        ));
+    }
+
+    #[test]
+    fn invalid_templates_are_config_errors() {
+        assert!(config_error("{name").contains("unclosed"));
+        assert!(config_error("name}").contains("unmatched"));
+        assert!(config_error("{}/{name}").contains("empty placeholder"));
+        assert!(config_error("{date:%Q}").contains("`%Q`"));
+        assert!(config_error("{date:%Y%}").contains("trailing"));
+        assert!(config_error("{sha256:65}").contains("1 to 64"));
+        assert!(config_error("{host:x}").contains("unknown placeholder `{host:x}`"));
+        assert!(config_error("/{name}").contains("absolute path"));
+        assert!(config_error("{host}//{name}").contains("empty path segment"));
+        assert!(config_error("../{name}").contains("`..`"));
+    }
+
+    #[test]
+    fn civil_dates() {
+        let utc = Utc::from_unix(1_709_251_199);
+        assert_eq!((utc.year, utc.month, utc.day), (2024, 2, 29));
+        assert_eq!((utc.hour, utc.minute, utc.second), (23, 59, 59));
+        assert_eq!(utc.day_of_year, 60);
+
+        let utc = Utc::from_unix(978_220_800);
+        assert_eq!(
+            (utc.year, utc.month, utc.day, utc.day_of_year),
+            (2000, 12, 31, 366)
+        );
+
+        let utc = Utc::from_unix(0);
+        assert_eq!(
+            (utc.year, utc.month, utc.day, utc.day_of_year),
+            (1970, 1, 1, 1)
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! Durable on-disk write-ahead log for queued items.
+//!
+//! When enabled via [`HtmlSaverBuilder::write_ahead_log`](crate::HtmlSaverBuilder::write_ahead_log),
+//! every item is appended to a segment file before `save` returns and is
+//! acknowledged once it has been written to storage (or to the dead-letter
+//! sink). Items that were never acknowledged -- because the process crashed
+//! or was killed -- are replayed automatically the next time a saver is built
+//! on the same directory.
+//!
+//! # On-disk layout
+//!
+//! - `<id>.seg` -- append-only segment of records
+//!   (`len: u32 | crc: u32 | seq: u64 | name_len: u32 | name | meta_len: u32 | meta | content`,
+//!   little endian, where `crc` is the CRC-32 of everything after it and
+//!   `meta` is the item's [`ObjectMetadata`](crate::ObjectMetadata) as JSON).
+//! - `<id>.ack` -- append-only list of acknowledged sequence numbers (`u64`)
+//!   for the segment with the same id.
+//!
+//! A new segment is started once the active one exceeds the configured
+//! [`segment_size`](WalConfig::segment_size). Fully acknowledged segments are
+//! deleted. On open, all still-pending records are compacted into a fresh
+//! segment and the old files are removed. A torn record at the end of a
+//! segment is dropped; a segment that is corrupt before its end is renamed to
+//! `<id>.seg.corrupt` (with its ack file) for inspection instead.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::storage::ObjectMetadata;
+
+const SEGMENT_EXT: &str = "seg";
+const ACK_EXT: &str = "ack";
+const CORRUPT_EXT: &str = "corrupt";
+/// Length and CRC-32 of the body.
+const HEADER_LEN: usize = 4 + 4;
+const FIXED_LEN: usize = 8 + 4 + 4;
+
+/// Configuration for the durable write-ahead log.
+///
+/// # Example
+///
+/// ```
+/// use html_saver::WalConfig;
+///
+/// let wal = WalConfig::new("/var/lib/html_saver/wal")
+///     .segment_size(16 * 1024 * 1024)
+///     .sync(true);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WalConfig {
+    dir: PathBuf,
+    segment_size: u64,
+    sync: bool,
+}
+
+impl WalConfig {
+    /// Store segments in the given directory. It is created if missing.
+    ///
+    /// Defaults: 64 MiB segments, no `fsync` after each append.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_size: 64 * 1024 * 1024,
+            sync: false,
+        }
+    }
+
+    /// Size in bytes after which the active segment is rotated.
+    pub fn segment_size(mut self, bytes: u64) -> Self {
+        self.segment_size = bytes;
+        self
+    }
+
+    /// `fsync` every append and acknowledgement.
+    ///
+    /// Without it, queued items survive a process crash but not a power loss
+    /// or kernel panic.
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+}
+
+/// An item recovered from the log that had not been acknowledged.
+#[derive(Debug)]
+pub(crate) struct Record {
+    pub seq: u64,
+    pub name: String,
+    pub metadata: ObjectMetadata,
+    pub content: Vec<u8>,
+}
+
+pub(crate) struct Wal {
+    config: WalConfig,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    active: File,
+    active_id: u64,
+    active_size: u64,
+    next_seq: u64,
+    /// Number of unacknowledged records per segment id.
+    live: BTreeMap<u64, usize>,
+    /// Segment id each unacknowledged sequence number lives in.
+    segment_of: HashMap<u64, u64>,
+    /// Open ack files, keyed by segment id.
+    ack_files: HashMap<u64, File>,
+}
+
+impl Wal {
+    /// Open (or create) the log, compacting pending records into a fresh
+    /// segment. Returns the log together with the records to replay.
+    pub fn open(config: WalConfig) -> io::Result<(Self, Vec<Record>)> {
+        fs::create_dir_all(&config.dir)?;
+
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(SEGMENT_EXT)
+                && let Some(id) = segment_id(&path)
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        let mut pending = Vec::new();
+        let mut corrupt = Vec::new();
+        for &id in &ids {
+            let acked = read_acks(&file_path(&config.dir, id, ACK_EXT))?;
+            let (records, complete) = read_segment(&file_path(&config.dir, id, SEGMENT_EXT))?;
+            if !complete {
+                corrupt.push(id);
+            }
+            for record in records {
+                if !acked.contains(&record.seq) {
+                    pending.push(record);
+                }
+            }
+        }
+
+        let active_id = ids.last().map_or(0, |id| id + 1);
+        let active = open_append(&file_path(&config.dir, active_id, SEGMENT_EXT))?;
+        let mut inner = Inner {
+            active,
+            active_id,
+            active_size: 0,
+            next_seq: 0,
+            live: BTreeMap::from([(active_id, 0)]),
+            segment_of: HashMap::new(),
+            ack_files: HashMap::new(),
+        };
+
+        let mut recovered = Vec::with_capacity(pending.len());
+        for record in pending {
+            let seq = inner.append(&record.name, &record.metadata, &record.content)?;
+            recovered.push(Record { seq, ..record });
+        }
+        inner.active.sync_all()?;
+
+        for id in ids {
+            let segment = file_path(&config.dir, id, SEGMENT_EXT);
+            let acks = file_path(&config.dir, id, ACK_EXT);
+            if corrupt.contains(&id) {
+                // The records after the corruption may still be recoverable
+                // by hand.
+                let quarantined = segment.with_extension(format!("{SEGMENT_EXT}.{CORRUPT_EXT}"));
+                tracing::error!(
+                    "WAL segment {} is corrupt; moved it to {}",
+                    segment.display(),
+                    quarantined.display()
+                );
+                fs::rename(&segment, quarantined)?;
+                if acks.exists() {
+                    fs::rename(
+                        &acks,
+                        acks.with_extension(format!("{ACK_EXT}.{CORRUPT_EXT}")),
+                    )?;
+                }
+                continue;
+            }
+            remove_if_exists(&segment)?;
+            remove_if_exists(&acks)?;
+        }
+
+        if !recovered.is_empty() {
+            tracing::info!(
+                "Recovered {} unacknowledged items from WAL",
+                recovered.len()
+            );
+        }
+
+        let wal = Self {
+            config,
+            inner: Mutex::new(inner),
+        };
+        Ok((wal, recovered))
+    }
+
+    /// Append an item, rotating the active segment if it grew too large.
+    /// Returns the sequence number to [`ack`](Self::ack) it with.
+    pub fn append(&self, name: &str, metadata: &ObjectMetadata, content: &[u8]) -> io::Result<u64> {
+        let mut inner = self.lock();
+        if inner.active_size >= self.config.segment_size {
+            inner.rotate(&self.config)?;
+        }
+        let seq = inner.append(name, metadata, content)?;
+        if self.config.sync {
+            inner.active.sync_data()?;
+        }
+        Ok(seq)
+    }
+
+    /// Mark an item as done. Segments whose items are all acknowledged are
+    /// deleted unless they are still being written to.
+    pub fn ack(&self, seq: u64) -> io::Result<()> {
+        let mut inner = self.lock();
+        let Some(id) = inner.segment_of.remove(&seq) else {
+            return Ok(());
+        };
+
+        let remaining = inner.live.get_mut(&id).map_or(0, |n| {
+            *n -= 1;
+            *n
+        });
+        if remaining == 0 && id != inner.active_id {
+            inner.live.remove(&id);
+            inner.ack_files.remove(&id);
+            remove_if_exists(&file_path(&self.config.dir, id, SEGMENT_EXT))?;
+            remove_if_exists(&file_path(&self.config.dir, id, ACK_EXT))?;
+            return Ok(());
+        }
+
+        let ack_file = match inner.ack_files.entry(id) {
+            std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(open_append(&file_path(&self.config.dir, id, ACK_EXT))?)
+            }
+        };
+        ack_file.write_all(&seq.to_le_bytes())?;
+        if self.config.sync {
+            ack_file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Inner {
+    fn append(&mut self, name: &str, metadata: &ObjectMetadata, content: &[u8]) -> io::Result<u64> {
+        let seq = self.next_seq;
+        let buf = encode_record(seq, name, metadata, content)?;
+        if let Err(e) = self.active.write_all(&buf) {
+            // Cut off a partial write (e.g. on a full disk) so that later
+            // records do not land behind unreadable bytes.
+            if let Err(truncate) = self.active.set_len(self.active_size) {
+                tracing::error!("Failed to truncate torn WAL append: {truncate}");
+                // Force a rotation before the next append.
+                self.active_size = u64::MAX;
+            }
+            return Err(e);
+        }
+
+        self.next_seq += 1;
+        self.active_size += buf.len() as u64;
+        *self.live.entry(self.active_id).or_insert(0) += 1;
+        self.segment_of.insert(seq, self.active_id);
+        Ok(seq)
+    }
+
+    fn rotate(&mut self, config: &WalConfig) -> io::Result<()> {
+        self.active.sync_all()?;
+        let previous = self.active_id;
+        self.active_id += 1;
+        self.active = open_append(&file_path(&config.dir, self.active_id, SEGMENT_EXT))?;
+        self.active_size = 0;
+        self.live.insert(self.active_id, 0);
+
+        if self.live.get(&previous) == Some(&0) {
+            self.live.remove(&previous);
+            self.ack_files.remove(&previous);
+            remove_if_exists(&file_path(&config.dir, previous, SEGMENT_EXT))?;
+            remove_if_exists(&file_path(&config.dir, previous, ACK_EXT))?;
+        }
+        tracing::debug!("Rotated WAL to segment {}", self.active_id);
+        Ok(())
+    }
+}
+
+fn file_path(dir: &Path, id: u64, ext: &str) -> PathBuf {
+    dir.join(format!("{id:020}.{ext}"))
+}
+
+fn segment_id(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn read_all(path: &Path) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    match File::open(path) {
+        Ok(mut f) => {
+            f.read_to_end(&mut buf)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    Ok(buf)
+}
+
+fn read_acks(path: &Path) -> io::Result<HashSet<u64>> {
+    Ok(read_all(path)?
+        .chunks_exact(8)
+        .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+        .collect())
+}
+
+/// Decode all records of a segment up to the first one that cannot be
+/// decoded, and tell whether the whole segment was read.
+///
+/// A torn record at the end (from a crash mid-append) is ignored and still
+/// counts as the whole segment. A bad record followed by more data means the
+/// segment is corrupt, and the records after it are not returned.
+fn read_segment(path: &Path) -> io::Result<(Vec<Record>, bool)> {
+    let data = read_all(path)?;
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let rest = &data[pos..];
+        let Some((record, len)) = decode_record(rest) else {
+            if is_torn_tail(rest) {
+                tracing::warn!("Ignoring torn record at end of {}", path.display());
+                break;
+            }
+            return Ok((records, false));
+        };
+        records.push(record);
+        pos += len;
+    }
+
+    Ok((records, true))
+}
+
+/// Whether an undecodable record at the start of `data` is the last thing in
+/// it: cut short, or exactly as long as its header claims.
+fn is_torn_tail(data: &[u8]) -> bool {
+    let Some(len) = data.get(..4) else {
+        return true;
+    };
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    HEADER_LEN.saturating_add(len) >= data.len()
+}
+
+/// Serialize a single record in the on-disk format.
+pub(crate) fn encode_record(
+    seq: u64,
+    name: &str,
+    metadata: &ObjectMetadata,
+    content: &[u8],
+) -> io::Result<Vec<u8>> {
+    let meta = serde_json::to_vec(metadata)?;
+    let len = FIXED_LEN + name.len() + meta.len() + content.len();
+    let len_u32 = u32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "item too large for WAL"))?;
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + len);
+    buf.extend_from_slice(&len_u32.to_le_bytes());
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&seq.to_le_bytes());
+    buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&meta);
+    buf.extend_from_slice(content);
+    let crc = crc32fast::hash(&buf[HEADER_LEN..]);
+    buf[4..HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+    Ok(buf)
+}
+
+/// Decode the record at the start of `data`, returning it with its encoded
+/// length, or `None` if `data` does not hold a complete record or its
+/// checksum does not match.
+pub(crate) fn decode_record(data: &[u8]) -> Option<(Record, usize)> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(data.get(4..HEADER_LEN)?.try_into().ok()?);
+    let body = data.get(HEADER_LEN..HEADER_LEN + len)?;
+    if len < FIXED_LEN || crc32fast::hash(body) != crc {
+        return None;
+    }
+    let seq = u64::from_le_bytes(body[..8].try_into().ok()?);
+    let name_len = u32::from_le_bytes(body[8..12].try_into().ok()?) as usize;
+    let name = body.get(12..12 + name_len)?;
+    let meta_start = 12 + name_len + 4;
+    let meta_len =
+        u32::from_le_bytes(body.get(12 + name_len..meta_start)?.try_into().ok()?) as usize;
+    let meta = body.get(meta_start..meta_start + meta_len)?;
+    let record = Record {
+        seq,
+        name: String::from_utf8_lossy(name).into_owned(),
+        metadata: serde_json::from_slice(meta).ok()?,
+        content: body[meta_start + meta_len..].to_vec(),
+    };
+    Some((record, HEADER_LEN + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html() -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+    }
+
+    fn names(records: &[Record]) -> Vec<&str> {
+        records.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn unacked_records_are_recovered() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert!(recovered.is_empty());
+
+        let a = wal.append("a.html", &html(), b"<p>a</p>").unwrap();
+        wal.append("b.html", &html(), b"<p>b</p>").unwrap();
+        wal.ack(a).unwrap();
+        drop(wal);
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(names(&recovered), vec!["b.html"]);
+        assert_eq!(recovered[0].content, b"<p>b</p>");
+    }
+
+    #[test]
+    fn recovered_records_survive_a_second_restart() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        wal.append("a.html", &html(), b"a").unwrap();
+        drop(wal);
+
+        let (wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(recovered.len(), 1);
+        drop(wal);
+
+        let (wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(names(&recovered), vec!["a.html"]);
+        wal.ack(recovered[0].seq).unwrap();
+        drop(wal);
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert!(recovered.is_empty());
+    }
+
+    #[test]
+    fn fully_acked_segments_are_deleted_after_rotation() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path()).segment_size(1)).unwrap();
+
+        let seqs: Vec<_> = (0..3)
+            .map(|i| wal.append(&format!("{i}.html"), &html(), b"x").unwrap())
+            .collect();
+        for seq in seqs {
+            wal.ack(seq).unwrap();
+        }
+
+        let segments = fs::read_dir(tmp.path())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension().unwrap() == SEGMENT_EXT)
+            .count();
+        assert_eq!(segments, 1, "only the active segment should remain");
+    }
+
+    #[test]
+    fn metadata_is_recovered() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        let metadata = html()
+            .user_metadata("source-url", "https://example.com/")
+            .tag("status", "200");
+        wal.append("a.html", &metadata, b"a").unwrap();
+        drop(wal);
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(recovered[0].metadata, metadata);
+        assert_eq!(recovered[0].content, b"a");
+    }
+
+    #[test]
+    fn torn_trailing_record_is_ignored() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        wal.append("ok.html", &html(), b"ok").unwrap();
+        drop(wal);
+
+        let segment = fs::read_dir(tmp.path())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let mut f = OpenOptions::new().append(true).open(segment).unwrap();
+        f.write_all(&100u32.to_le_bytes()).unwrap();
+        f.write_all(b"partial").unwrap();
+        drop(f);
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(names(&recovered), vec!["ok.html"]);
+    }
+
+    /// Path of the only segment in `dir`.
+    fn only_segment(dir: &Path) -> PathBuf {
+        let mut segments: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().unwrap() == SEGMENT_EXT)
+            .collect();
+        assert_eq!(segments.len(), 1);
+        segments.pop().unwrap()
+    }
+
+    #[test]
+    fn zero_filled_trailing_record_is_ignored() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        wal.append("ok.html", &html(), b"ok").unwrap();
+        drop(wal);
+
+        // A crash after the length made it to disk but before the body did.
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(only_segment(tmp.path()))
+            .unwrap();
+        f.write_all(&64u32.to_le_bytes()).unwrap();
+        f.write_all(&[0; 68]).unwrap();
+        drop(f);
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(names(&recovered), vec!["ok.html"]);
+        assert_eq!(recovered[0].content, b"ok");
+    }
+
+    #[test]
+    fn corrupt_segments_are_quarantined() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (wal, _) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        for name in ["a.html", "b.html", "c.html"] {
+            wal.append(name, &html(), b"content").unwrap();
+        }
+        drop(wal);
+
+        // Flip the last byte of the middle record.
+        let segment = only_segment(tmp.path());
+        let mut data = fs::read(&segment).unwrap();
+        let (_, first) = decode_record(&data).unwrap();
+        let (_, second) = decode_record(&data[first..]).unwrap();
+        data[first + second - 1] ^= 0xff;
+        fs::write(&segment, &data).unwrap();
+
+        let (_wal, recovered) = Wal::open(WalConfig::new(tmp.path())).unwrap();
+        assert_eq!(names(&recovered), vec!["a.html"]);
+        let quarantined = segment.with_extension("seg.corrupt");
+        assert_eq!(fs::read(quarantined).unwrap(), data);
+    }
+}
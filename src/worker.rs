@@ -1,28 +1,179 @@
-//! Background worker that batches and flushes [`Saveable`] items to storage.
+//! Background worker that batches, sanitizes and uploads [`Saveable`] items.
 //!
 //! This module is internal -- users interact with it indirectly through
 //! [`HtmlSaverHandle`](crate::HtmlSaverHandle).
 
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
+use futures::StreamExt;
+use futures::stream::{FuturesOrdered, FuturesUnordered};
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::{self, MissedTickBehavior};
 
+use crate::compression::Compression;
+use crate::dead_letter::{self, DeadLetterRecord};
+use crate::dedup::Dedup;
+use crate::error::{HtmlSaverError, Result};
+use crate::key::{KeyPolicy, validate_key};
+use crate::overflow::Spill;
+use crate::overwrite::OverwritePolicy;
+use crate::retry::RetryPolicy;
 use crate::sanitizer::SanitizerPipeline;
+use crate::sanitizer::executor::Runner;
 use crate::saveable::Saveable;
-use crate::storage::Storage;
+use crate::storage::{DynStorage, ObjectMetadata, Storage};
+use crate::wal::{Record, Wal};
 
+/// An item travelling through the channel with its name (the rendered key
+/// template, if one is set), tagged with its write-ahead log sequence number
+/// when journaling is enabled and with the channel to report the outcome on
+/// when the caller asked for an acknowledgement.
+pub(crate) struct Queued<R> {
+    pub item: R,
+    pub name: String,
+    pub seq: Option<u64>,
+    pub ack: Option<oneshot::Sender<Result<String>>>,
+}
+
+/// Receiving end of the channel, shared so that the
+/// [drop-oldest](crate::OverflowPolicy::DropOldest) policy can evict items.
+///
+/// The lock is only held while polling, never across an `.await`.
+pub(crate) struct SharedReceiver<R>(Arc<Mutex<mpsc::Receiver<Queued<R>>>>);
+
+impl<R> SharedReceiver<R> {
+    pub fn new(rx: mpsc::Receiver<Queued<R>>) -> Self {
+        Self(Arc::new(Mutex::new(rx)))
+    }
+
+    pub fn downgrade(&self) -> Weak<Mutex<mpsc::Receiver<Queued<R>>>> {
+        Arc::downgrade(&self.0)
+    }
+
+    async fn recv(&self) -> Option<Queued<R>> {
+        std::future::poll_fn(|cx| self.lock().poll_recv(cx)).await
+    }
+
+    fn close(&self) {
+        self.lock().close();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, mpsc::Receiver<Queued<R>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Everything the worker needs besides its channels.
+pub(crate) struct WorkerConfig<S: Storage> {
+    pub storage: S,
+    pub preparer: Arc<Preparer>,
+    pub sanitizer_runner: Runner,
+    pub sanitizer_parallelism: usize,
+    pub batch_size: usize,
+    pub max_concurrent_uploads: usize,
+    pub sanitize_buffer: usize,
+    pub upload_buffer: usize,
+    pub flush_interval: Duration,
+    pub retry: RetryPolicy,
+    pub overwrite: OverwritePolicy,
+    pub dedup: Option<Dedup>,
+    pub dead_letter: Option<Box<dyn DynStorage>>,
+    pub wal: Option<Arc<Wal>>,
+    pub spill: Option<Arc<Spill>>,
+}
+
+/// Work handed from the receive stage to the sanitize stage.
+enum Job<R> {
+    /// An item sent through the channel.
+    Queued(Queued<R>),
+    /// An unacknowledged item replayed from the write-ahead log.
+    Recovered(Record),
+    /// An item spilled to disk by the overflow policy, by id and path.
+    Spilled(u64, PathBuf),
+}
+
+impl<R> Job<R> {
+    /// Take out the write-ahead log sequence number and where to report the
+    /// outcome, so they survive a failure while preparing the job.
+    fn take_reply(&mut self) -> (Option<u64>, Reply) {
+        match self {
+            Self::Queued(queued) => (
+                queued.seq.take(),
+                queued.ack.take().map_or(Reply::None, Reply::Ack),
+            ),
+            Self::Recovered(record) => (Some(record.seq), Reply::None),
+            Self::Spilled(id, _) => (None, Reply::Spill(*id)),
+        }
+    }
+}
+
+/// A sanitized item handed from the sanitize stage to the upload stage.
+struct Prepared {
+    /// The storage key, or why the item cannot be stored: its name is not a
+    /// valid key or the sanitizer failed.
+    key: Result<String>,
+    content: Vec<u8>,
+    metadata: ObjectMetadata,
+    seq: Option<u64>,
+    reply: Reply,
+}
+
+/// Where to report the outcome of an upload.
+enum Reply {
+    None,
+    Ack(oneshot::Sender<Result<String>>),
+    Spill(u64),
+}
+
+/// Run the worker as three stages connected by bounded queues:
+///
+/// 1. **receive** -- batches items from the channel (plus WAL and spill
+///    replays) and forwards flushed batches to the sanitize queue;
+/// 2. **sanitize** -- runs the [`SanitizerPipeline`] on the configured
+///    [`SanitizerExecutor`](crate::SanitizerExecutor), up to
+///    `sanitizer_parallelism` items at once, compresses the result and builds
+///    the storage key;
+/// 3. **upload** -- writes items with at most `max_concurrent_uploads` in
+///    flight.
+///
+/// A slow stage only stalls the one before it once the queue between them is
+/// full. On shutdown each stage drains its input and closes its output, so the
+/// worker returns once every item has been uploaded.
 pub async fn run<S: Storage, R: Saveable>(
-    mut rx: mpsc::Receiver<R>,
+    rx: SharedReceiver<R>,
+    shutdown_rx: oneshot::Receiver<()>,
+    config: WorkerConfig<S>,
+    recovered: Vec<Record>,
+) {
+    let (sanitize_tx, sanitize_rx) = mpsc::channel(config.sanitize_buffer.max(1));
+    let (upload_tx, upload_rx) = mpsc::channel(config.upload_buffer.max(1));
+
+    tokio::join!(
+        receive_stage(&config, rx, shutdown_rx, sanitize_tx, recovered),
+        sanitize_stage(&config, sanitize_rx, upload_tx),
+        upload_stage(&config, upload_rx),
+    );
+    tracing::info!("Worker shut down");
+}
+
+async fn receive_stage<S: Storage, R: Saveable>(
+    config: &WorkerConfig<S>,
+    rx: SharedReceiver<R>,
     mut shutdown_rx: oneshot::Receiver<()>,
-    storage: S,
-    sanitizers: SanitizerPipeline,
-    prefix: String,
-    batch_size: usize,
-    flush_interval: Duration,
+    tx: mpsc::Sender<Job<R>>,
+    recovered: Vec<Record>,
 ) {
-    let mut batch: Vec<R> = Vec::with_capacity(batch_size);
-    let mut interval = time::interval(flush_interval);
+    if !recovered.is_empty() {
+        tracing::info!("Replaying {} items from WAL", recovered.len());
+        for record in recovered {
+            let _ = tx.send(Job::Recovered(record)).await;
+        }
+    }
+
+    let mut batch: Vec<Queued<R>> = Vec::with_capacity(config.batch_size);
+    let mut interval = time::interval(config.flush_interval);
     interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
     // Skip the first immediate tick
     interval.tick().await;
@@ -38,60 +189,412 @@ pub async fn run<S: Storage, R: Saveable>(
                 while let Some(item) = rx.recv().await {
                     batch.push(item);
                 }
-                if !batch.is_empty() {
-                    flush_batch(&storage, &sanitizers, &prefix, &mut batch).await;
-                }
-                tracing::info!("Worker shut down");
+                flush_batch(&tx, &mut batch).await;
+                drain_spill(config, &tx).await;
                 return;
             }
 
             Some(item) = rx.recv() => {
                 batch.push(item);
-                if batch.len() >= batch_size {
-                    flush_batch(&storage, &sanitizers, &prefix, &mut batch).await;
+                if batch.len() >= config.batch_size {
+                    flush_batch(&tx, &mut batch).await;
                 }
             }
 
             _ = interval.tick() => {
-                if !batch.is_empty() {
-                    flush_batch(&storage, &sanitizers, &prefix, &mut batch).await;
-                }
+                flush_batch(&tx, &mut batch).await;
+                drain_spill(config, &tx).await;
             }
         }
     }
 }
 
-async fn flush_batch<S: Storage, R: Saveable>(
-    storage: &S,
-    sanitizers: &SanitizerPipeline,
-    prefix: &str,
-    batch: &mut Vec<R>,
+/// Forward the current batch to the sanitize stage, waiting for room in its
+/// queue.
+async fn flush_batch<R>(tx: &mpsc::Sender<Job<R>>, batch: &mut Vec<Queued<R>>) {
+    if batch.is_empty() {
+        return;
+    }
+    tracing::debug!("Flushing batch of {} items", batch.len());
+    for queued in batch.drain(..) {
+        let _ = tx.send(Job::Queued(queued)).await;
+    }
+}
+
+/// Forward items spilled to disk by the
+/// [spill-to-disk](crate::OverflowPolicy::SpillToDisk) policy to the sanitize
+/// stage.
+async fn drain_spill<S: Storage, R>(config: &WorkerConfig<S>, tx: &mpsc::Sender<Job<R>>) {
+    let Some(spill) = &config.spill else {
+        return;
+    };
+    let pending = match spill.claim_pending() {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!("Failed to list spilled items: {e}");
+            return;
+        }
+    };
+    if pending.is_empty() {
+        return;
+    }
+    tracing::debug!("Draining {} spilled items", pending.len());
+    for (id, path) in pending {
+        let _ = tx.send(Job::Spilled(id, path)).await;
+    }
+}
+
+async fn sanitize_stage<S: Storage, R: Saveable>(
+    config: &WorkerConfig<S>,
+    mut rx: mpsc::Receiver<Job<R>>,
+    tx: mpsc::Sender<Prepared>,
 ) {
-    let items: Vec<R> = std::mem::take(batch);
-    let count = items.len();
-    tracing::debug!("Flushing batch of {count} items");
-
-    let futs = items.iter().map(|item| {
-        let content = if sanitizers.is_empty() {
-            item.content().to_string()
-        } else {
-            sanitizers.sanitize(item.content())
+    let limit = config.sanitizer_parallelism.max(1);
+    // Ordered, so items reach the upload stage in the order they were received.
+    let mut running = FuturesOrdered::new();
+
+    loop {
+        tokio::select! {
+            Some(prepared) = running.next(), if !running.is_empty() => {
+                if let Some(prepared) = prepared {
+                    let _ = tx.send(prepared).await;
+                }
+            }
+
+            job = rx.recv(), if running.len() < limit => match job {
+                Some(job) => running.push_back(sanitize(config, job)),
+                None => break,
+            },
+        }
+    }
+
+    while let Some(prepared) = running.next().await {
+        if let Some(prepared) = prepared {
+            let _ = tx.send(prepared).await;
+        }
+    }
+}
+
+/// Run [`Preparer::prepare`] on the configured
+/// [`SanitizerExecutor`](crate::SanitizerExecutor).
+///
+/// Without sanitizers or compression there is nothing CPU-heavy to offload,
+/// so the job is prepared in place. If the sanitizer panics, the failure is
+/// handed to the upload stage to be reported.
+async fn sanitize<S: Storage, R: Saveable>(
+    config: &WorkerConfig<S>,
+    mut job: Job<R>,
+) -> Option<Prepared> {
+    if config.preparer.is_cheap() {
+        return config.preparer.prepare(job);
+    }
+
+    let (seq, reply) = job.take_reply();
+    let preparer = config.preparer.clone();
+    match config
+        .sanitizer_runner
+        .run(move || preparer.prepare(job))
+        .await
+    {
+        Ok(prepared) => prepared.map(|prepared| Prepared {
+            seq,
+            reply,
+            ..prepared
+        }),
+        Err(e) => {
+            tracing::error!("Sanitizer task failed: {e}");
+            Some(Prepared {
+                key: Err(e),
+                content: Vec::new(),
+                // Unused: the item is dropped.
+                metadata: ObjectMetadata::new(""),
+                seq,
+                reply,
+            })
+        }
+    }
+}
+
+/// Turns jobs into [`Prepared`] items; shared with the sanitizer executor.
+pub(crate) struct Preparer {
+    pub sanitizers: SanitizerPipeline,
+    pub prefix: String,
+    pub key_policy: KeyPolicy,
+    pub compression: Compression,
+    pub spill: Option<Arc<Spill>>,
+}
+
+impl Preparer {
+    fn is_cheap(&self) -> bool {
+        self.sanitizers.is_empty() && self.compression == Compression::None
+    }
+
+    /// Sanitize and compress a job's content and build its storage key,
+    /// applying the [`KeyPolicy`].
+    ///
+    /// Returns `None` if a spilled item cannot be read; it is moved aside if
+    /// it is corrupt and retried later otherwise.
+    fn prepare<R: Saveable>(&self, job: Job<R>) -> Option<Prepared> {
+        let (name, content, mut metadata, seq, reply) = match job {
+            Job::Queued(queued) => {
+                let reply = queued.ack.map_or(Reply::None, Reply::Ack);
+                let item = queued.item;
+                let metadata = item.metadata();
+                let content = self.sanitize(&metadata.content_type, item.content_bytes());
+                (queued.name, content, metadata, queued.seq, reply)
+            }
+            Job::Recovered(record) => {
+                let content = self.sanitize(&record.metadata.content_type, &record.content);
+                (
+                    record.name,
+                    content,
+                    record.metadata,
+                    Some(record.seq),
+                    Reply::None,
+                )
+            }
+            Job::Spilled(id, path) => {
+                let spill = self.spill.as_ref()?;
+                match spill.read(&path) {
+                    Ok(record) => {
+                        let content = self.sanitize(&record.metadata.content_type, &record.content);
+                        (
+                            record.name,
+                            content,
+                            record.metadata,
+                            None,
+                            Reply::Spill(id),
+                        )
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to read spilled item {}: {e}", path.display());
+                        let ack = if e.kind() == std::io::ErrorKind::InvalidData {
+                            spill.quarantine(id)
+                        } else {
+                            spill.retry_later(id)
+                        };
+                        if let Some(ack) = ack {
+                            let _ = ack.send(Err(HtmlSaverError::Spill(e)));
+                        }
+                        return None;
+                    }
+                }
+            }
         };
 
-        let key = if prefix.is_empty() {
-            item.name()
-        } else {
-            format!("{}/{}", prefix, item.name())
+        // The policy applies to the name alone, so normalizing `..` never
+        // climbs out of the prefix.
+        let key = self.key_policy.apply(name).and_then(|name| {
+            if self.prefix.is_empty() {
+                return Ok(name);
+            }
+            let key = format!("{}/{}", self.prefix, name);
+            validate_key(&key).map(|()| key)
+        });
+        let mut key = match key {
+            Ok(key) => key,
+            Err(e) => {
+                return Some(Prepared {
+                    key: Err(e),
+                    content,
+                    metadata,
+                    seq,
+                    reply,
+                });
+            }
+        };
+        metadata.content_encoding = None;
+
+        let content = match self.compression.compress(&content) {
+            Ok(compressed) => {
+                if let Some(encoding) = self.compression.content_encoding() {
+                    key.push_str(self.compression.extension());
+                    metadata.content_encoding = Some(encoding.to_string());
+                }
+                compressed
+            }
+            Err(e) => {
+                // In-memory encoders do not fail in practice; keep the item
+                // rather than losing it.
+                tracing::error!("Failed to compress {key}, storing it uncompressed: {e}");
+                content
+            }
         };
 
-        let storage = &storage;
-        async move {
-            if let Err(e) = storage.put(&key, content.as_bytes(), "text/html").await {
-                tracing::error!("Failed to upload {key}: {e}");
+        Some(Prepared {
+            key: Ok(key),
+            content,
+            metadata,
+            seq,
+            reply,
+        })
+    }
+
+    /// Run the sanitizers that apply to `content_type`; content no sanitizer
+    /// applies to, including all binary content, is passed through untouched.
+    fn sanitize(&self, content_type: &str, content: &[u8]) -> Vec<u8> {
+        if !self.sanitizers.applies_to(content_type) {
+            return content.to_vec();
+        }
+        self.sanitizers
+            .sanitize_as(content_type, &String::from_utf8_lossy(content))
+            .into_bytes()
+    }
+}
+
+async fn upload_stage<S: Storage>(config: &WorkerConfig<S>, mut rx: mpsc::Receiver<Prepared>) {
+    let limit = config.max_concurrent_uploads.max(1);
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        tokio::select! {
+            Some(()) = running.next(), if !running.is_empty() => {}
+
+            // Only take the next item once an upload slot is free.
+            prepared = rx.recv(), if running.len() < limit => match prepared {
+                Some(prepared) => running.push(upload(config, prepared)),
+                None => break,
+            },
+        }
+    }
+
+    while running.next().await.is_some() {}
+}
+
+/// Upload a prepared item, report the outcome and acknowledge it in the
+/// write-ahead log.
+async fn upload<S: Storage>(config: &WorkerConfig<S>, prepared: Prepared) {
+    let Prepared {
+        key,
+        content,
+        metadata,
+        seq,
+        reply,
+    } = prepared;
+    let (result, done) = match key {
+        Ok(key) => store(config, key, &content, &metadata, seq).await,
+        Err(e) => {
+            // Neither retrying nor dead-lettering can fix the key or make the
+            // sanitizer succeed.
+            tracing::error!("Dropping item: {e}");
+            let key = match &e {
+                HtmlSaverError::InvalidKey { key, .. } => key.as_str(),
+                _ => "dropped item",
+            };
+            ack_wal(config, key, seq);
+            (Err(e), true)
+        }
+    };
+    let ack = match reply {
+        Reply::None => None,
+        Reply::Ack(ack) => Some(ack),
+        // The spill file is the item's only durable copy, even with a WAL.
+        Reply::Spill(id) => config.spill.as_ref().and_then(|spill| {
+            if done {
+                spill.complete(id)
+            } else {
+                spill.retry_later(id)
+            }
+        }),
+    };
+    if let Some(ack) = ack {
+        let _ = ack.send(result);
+    }
+}
+
+/// Upload and -- on permanent failure -- dead-letter a single item, then
+/// acknowledge it in the write-ahead log.
+///
+/// Returns the storage key on success, or the last upload error, together
+/// with whether the item is done with: uploaded, dead-lettered, or failed
+/// with an error the [`RetryPolicy`] does not consider retryable, which a
+/// later replay would only run into again.
+async fn store<S: Storage>(
+    config: &WorkerConfig<S>,
+    key: String,
+    content: &[u8],
+    metadata: &ObjectMetadata,
+    seq: Option<u64>,
+) -> (Result<String>, bool) {
+    let (result, done) = match put_with_retry(config, &key, content, metadata).await {
+        Ok(stored) => (Ok(stored), true),
+        Err((e, attempts)) => {
+            tracing::error!("Failed to upload {key} after {attempts} attempt(s): {e}");
+            let dead_lettered = match &config.dead_letter {
+                Some(dead_letter) => {
+                    let record = DeadLetterRecord::new(&key, metadata, &e, attempts);
+                    match dead_letter::write(dead_letter.as_ref(), &record, content).await {
+                        Ok(()) => true,
+                        Err(e) => {
+                            tracing::error!("Failed to dead-letter {key}: {e}");
+                            false
+                        }
+                    }
+                }
+                None => false,
+            };
+            let retryable = config.retry.is_retryable(&e);
+            if !dead_lettered && !retryable {
+                tracing::error!("Dropping {key}: the error is not retryable");
             }
+            (Err(e), dead_lettered || !retryable)
         }
-    });
+    };
 
-    futures::future::join_all(futs).await;
-    tracing::debug!("Flushed {count} items");
+    if done {
+        ack_wal(config, &key, seq);
+    }
+
+    (result, done)
+}
+
+/// Remove a finished item from the write-ahead log.
+fn ack_wal<S: Storage>(config: &WorkerConfig<S>, key: &str, seq: Option<u64>) {
+    if let (Some(wal), Some(seq)) = (&config.wal, seq)
+        && let Err(e) = wal.ack(seq)
+    {
+        tracing::error!("Failed to acknowledge {key} in WAL: {e}");
+    }
+}
+
+/// Upload a single item under the configured [`OverwritePolicy`], through
+/// [deduplication](crate::dedup) if enabled, retrying according to the
+/// configured [`RetryPolicy`].
+///
+/// Returns the key the item was stored under or, on failure, the last error
+/// together with the number of attempts made.
+async fn put_with_retry<S: Storage>(
+    config: &WorkerConfig<S>,
+    key: &str,
+    content: &[u8],
+    metadata: &ObjectMetadata,
+) -> std::result::Result<String, (HtmlSaverError, u32)> {
+    let mut attempt = 1;
+    loop {
+        let result = match &config.dedup {
+            Some(dedup) => {
+                dedup
+                    .put(&config.storage, &config.overwrite, key, content, metadata)
+                    .await
+            }
+            None => {
+                config
+                    .overwrite
+                    .put(&config.storage, key, content, metadata)
+                    .await
+            }
+        };
+        match result {
+            Ok(stored) => return Ok(stored),
+            Err(e) if config.retry.should_retry(&e, attempt) => {
+                let delay = config.retry.delay(attempt);
+                tracing::warn!(
+                    "Upload of {key} failed (attempt {attempt}): {e}; retrying in {delay:?}"
+                );
+                time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err((e, attempt)),
+        }
+    }
 }
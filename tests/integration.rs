@@ -1,9 +1,14 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
+use html_saver::dead_letter::{self, DeadLetterRecord};
+use html_saver::dedup::{self, Manifest};
 use html_saver::{
-    FsStorage, HtmlSaverBuilder, HtmlSaverError, RegexSanitizer, Saveable, SelectorAction,
-    SelectorSanitizer, Storage, SubstringSanitizer,
+    DedupConfig, FaultyStorage, FsStorage, HtmlSaverBuilder, HtmlSaverError, KeyPolicy, KeySuffix,
+    MemoryStorage, ObjectMetadata, OverflowPolicy, OverwritePolicy, RegexSanitizer, RetryPolicy,
+    Sanitizer, SanitizerExecutor, SaveError, Saveable, SelectorAction, SelectorSanitizer, Storage,
+    SubstringSanitizer, WalConfig,
 };
 use tempfile::TempDir;
 use tokio::sync::Mutex as TokioMutex;
@@ -52,43 +57,122 @@ impl Saveable for SimpleDoc {
     }
 }
 
-/// In-memory storage for testing without touching the filesystem.
+/// Contents of a [`MemoryStorage`] as `(key, content)` pairs, sorted by key.
+fn objects(storage: &MemoryStorage) -> Vec<(String, Vec<u8>)> {
+    storage
+        .keys()
+        .into_iter()
+        .map(|key| {
+            let content = storage.get(&key).unwrap().content;
+            (key, content)
+        })
+        .collect()
+}
+
+/// All regular files below `dir`, relative to it and sorted.
+fn files_under(dir: &std::path::Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let relative = path.strip_prefix(dir).unwrap();
+                files.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Storage whose every write fails -- for testing error paths.
+fn failing() -> FaultyStorage<MemoryStorage> {
+    FaultyStorage::new(MemoryStorage::new()).fail_every(1)
+}
+
+/// Storage whose uploads never complete -- for keeping the worker busy.
+#[derive(Clone)]
+struct StalledStorage;
+
+impl Storage for StalledStorage {
+    async fn put(
+        &self,
+        _key: &str,
+        _content: &[u8],
+        _metadata: &ObjectMetadata,
+    ) -> html_saver::Result<()> {
+        std::future::pending().await
+    }
+}
+
+/// Storage whose uploads wait for a permit before delegating to an inner
+/// [`MemoryStorage`] -- for holding the worker busy in a controlled way.
 #[derive(Clone)]
-#[allow(clippy::type_complexity)]
-struct MemoryStorage {
-    files: Arc<TokioMutex<Vec<(String, Vec<u8>)>>>,
+struct GatedStorage {
+    inner: MemoryStorage,
+    gate: Arc<tokio::sync::Semaphore>,
 }
 
-impl MemoryStorage {
+impl GatedStorage {
     fn new() -> Self {
         Self {
-            files: Arc::new(TokioMutex::new(Vec::new())),
+            inner: MemoryStorage::new(),
+            gate: Arc::new(tokio::sync::Semaphore::new(0)),
         }
     }
 }
 
-impl Storage for MemoryStorage {
-    async fn put(&self, key: &str, content: &[u8], _content_type: &str) -> html_saver::Result<()> {
-        self.files
-            .lock()
-            .await
-            .push((key.to_string(), content.to_vec()));
-        Ok(())
+impl Storage for GatedStorage {
+    async fn put(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> html_saver::Result<()> {
+        self.gate.acquire().await.unwrap().forget();
+        self.inner.put(key, content, metadata).await
     }
 }
 
-/// Storage that always fails -- for testing error paths.
+/// Storage that fails the first `failures` puts of every key, then delegates
+/// to an inner [`MemoryStorage`].
 #[derive(Clone)]
-struct FailingStorage;
+struct FlakyStorage {
+    inner: MemoryStorage,
+    failures: u32,
+    attempts: Arc<TokioMutex<HashMap<String, u32>>>,
+}
+
+impl FlakyStorage {
+    fn new(failures: u32) -> Self {
+        Self {
+            inner: MemoryStorage::new(),
+            failures,
+            attempts: Arc::new(TokioMutex::new(HashMap::new())),
+        }
+    }
+}
 
-impl Storage for FailingStorage {
+impl Storage for FlakyStorage {
     async fn put(
         &self,
-        _key: &str,
-        _content: &[u8],
-        _content_type: &str,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
     ) -> html_saver::Result<()> {
-        Err(HtmlSaverError::StorageUpload("simulated failure".into()))
+        let attempt = {
+            let mut attempts = self.attempts.lock().await;
+            let n = attempts.entry(key.to_string()).or_insert(0);
+            *n += 1;
+            *n
+        };
+        if attempt <= self.failures {
+            return Err(HtmlSaverError::StorageUpload("throttled".into()));
+        }
+        self.inner.put(key, content, metadata).await
     }
 }
 
@@ -143,7 +227,7 @@ async fn fs_storage_write_and_read() {
 
     let content = b"<html><body>Test page</body></html>";
     storage
-        .put("test.html", content, "text/html")
+        .put("test.html", content, &ObjectMetadata::new("text/html"))
         .await
         .unwrap();
 
@@ -162,7 +246,7 @@ async fn fs_storage_nested_paths() {
         .put(
             "2024-01-15/12-30-00_200_abc.html",
             b"<p>nested</p>",
-            "text/html",
+            &ObjectMetadata::new("text/html"),
         )
         .await
         .unwrap();
@@ -182,7 +266,7 @@ async fn fs_storage_deeply_nested_paths() {
         .put(
             "clients/42/2024/01/15/result.html",
             b"<div>deep</div>",
-            "text/html",
+            &ObjectMetadata::new("text/html"),
         )
         .await
         .unwrap();
@@ -202,7 +286,7 @@ async fn fs_storage_concurrent_writes() {
             s.put(
                 &format!("file_{i}.html"),
                 format!("<p>{i}</p>").as_bytes(),
-                "text/html",
+                &ObjectMetadata::new("text/html"),
             )
             .await
             .unwrap();
@@ -220,6 +304,67 @@ async fn fs_storage_concurrent_writes() {
     }
 }
 
+#[tokio::test]
+async fn fs_storage_lists_and_deletes_saved_documents() {
+    use futures::TryStreamExt;
+
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()))
+        .prefix("crawl")
+        .build::<SimpleDoc>();
+    for i in 0..3 {
+        handle
+            .save(SimpleDoc {
+                name: format!("doc_{i}"),
+                html: format!("<p>{i}</p>"),
+            })
+            .unwrap();
+    }
+    handle.shutdown().await;
+
+    let storage = FsStorage::new(tmp.path());
+    let mut keys: Vec<String> = storage
+        .list("crawl/")
+        .map_ok(|info| info.key)
+        .try_collect()
+        .await
+        .unwrap();
+    keys.sort();
+    assert_eq!(keys.len(), 3);
+
+    let object = storage.get(&keys[0]).await.unwrap().unwrap();
+    assert_eq!(object.content, b"<p>0</p>");
+    for key in &keys {
+        storage.delete(key).await.unwrap();
+    }
+    let remaining: Vec<_> = storage.list("").try_collect().await.unwrap();
+    assert!(remaining.is_empty());
+}
+
+#[tokio::test]
+async fn custom_storage_reads_are_unsupported_by_default() {
+    use futures::StreamExt;
+
+    let storage = StalledStorage;
+    assert!(matches!(
+        storage.get("a.html").await,
+        Err(HtmlSaverError::Unsupported("get"))
+    ));
+    assert!(matches!(
+        storage.exists("a.html").await,
+        Err(HtmlSaverError::Unsupported(_))
+    ));
+    assert!(matches!(
+        storage.delete("a.html").await,
+        Err(HtmlSaverError::Unsupported("delete"))
+    ));
+    let listed: Vec<_> = storage.list("").collect().await;
+    assert!(matches!(
+        listed[..],
+        [Err(HtmlSaverError::Unsupported("list"))]
+    ));
+}
+
 // ---------------------------------------------------------------------------
 // End-to-end: HtmlSaver with FsStorage
 // ---------------------------------------------------------------------------
@@ -259,7 +404,7 @@ async fn e2e_fs_save_multiple_documents() {
 #[tokio::test]
 async fn e2e_batch_flush_by_size() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(2)
@@ -275,7 +420,7 @@ async fn e2e_batch_flush_by_size() {
 
     // Not yet flushed (batch_size=2, only 1 sent)
     tokio::time::sleep(Duration::from_millis(50)).await;
-    assert_eq!(files.lock().await.len(), 0);
+    assert_eq!(files.len(), 0);
 
     handle
         .save(SimpleDoc {
@@ -286,7 +431,7 @@ async fn e2e_batch_flush_by_size() {
 
     // Now batch_size reached, should flush
     tokio::time::sleep(Duration::from_millis(100)).await;
-    assert_eq!(files.lock().await.len(), 2);
+    assert_eq!(files.len(), 2);
 
     handle.shutdown().await;
 }
@@ -294,7 +439,7 @@ async fn e2e_batch_flush_by_size() {
 #[tokio::test]
 async fn e2e_batch_flush_by_interval() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(100) // Very large, so only interval triggers flush
@@ -311,18 +456,17 @@ async fn e2e_batch_flush_by_interval() {
     // Wait for interval to trigger
     tokio::time::sleep(Duration::from_millis(300)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(stored[0].0, "interval.html");
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn e2e_sanitizer_pipeline_applied() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -354,7 +498,7 @@ async fn e2e_sanitizer_pipeline_applied() {
 
     tokio::time::sleep(Duration::from_millis(150)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     let content = String::from_utf8_lossy(&stored[0].1);
     assert!(!content.contains("<script"));
@@ -363,14 +507,13 @@ async fn e2e_sanitizer_pipeline_applied() {
     assert!(content.contains("[REDACTED]"));
     assert!(content.contains("[EMAIL]"));
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn e2e_prefix_prepended() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -386,18 +529,17 @@ async fn e2e_prefix_prepended() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(stored[0].0, "scrapes/production/page.html");
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn e2e_prefix_with_saveable_nested_name() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -417,21 +559,20 @@ async fn e2e_prefix_with_saveable_nested_name() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(
         stored[0].0,
         "html_dumps/acme/2024-03-20/09-15-00_200_product_list.html"
     );
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn e2e_graceful_shutdown_drains_remaining() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(100) // Large batch, won't flush by size
@@ -451,14 +592,14 @@ async fn e2e_graceful_shutdown_drains_remaining() {
     // Shutdown should drain them
     handle.shutdown().await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 5, "all items should be drained on shutdown");
 }
 
 #[tokio::test]
 async fn e2e_sender_clone_from_multiple_tasks() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(100)
@@ -509,10 +650,9 @@ async fn e2e_sender_clone_from_multiple_tasks() {
     // Wait for interval flush
     tokio::time::sleep(Duration::from_millis(200)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 9, "all 9 items from 3 tasks should be stored");
 
-    drop(stored);
     handle.shutdown().await;
 }
 
@@ -556,7 +696,7 @@ async fn e2e_fs_with_prefix_and_scraping_result() {
 #[tokio::test]
 async fn edge_empty_html_content() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -571,18 +711,17 @@ async fn edge_empty_html_content() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(stored[0].1, b"");
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn edge_special_characters_in_content() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -599,18 +738,17 @@ async fn edge_special_characters_in_content() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(String::from_utf8_lossy(&stored[0].1), special_html);
 
-    drop(stored);
     handle.shutdown().await;
 }
 
 #[tokio::test]
 async fn edge_long_file_name() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -626,11 +764,10 @@ async fn edge_long_file_name() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(stored[0].0, long_name);
 
-    drop(stored);
     handle.shutdown().await;
 }
 
@@ -663,8 +800,9 @@ async fn edge_channel_full_small_buffer() {
         name: "3.html".into(),
         html: "<p>3</p>".into(),
     });
-    assert!(result.is_err());
-    assert!(matches!(result.unwrap_err(), HtmlSaverError::ChannelClosed));
+    let err = result.unwrap_err();
+    assert!(err.is_full());
+    assert_eq!(err.into_inner().name, "3.html");
 
     handle.shutdown().await;
 }
@@ -672,7 +810,7 @@ async fn edge_channel_full_small_buffer() {
 #[tokio::test]
 async fn edge_no_prefix_no_sanitizer() {
     let storage = MemoryStorage::new();
-    let files = storage.files.clone();
+    let files = storage.clone();
 
     let handle = HtmlSaverBuilder::new(storage)
         .batch_size(1)
@@ -687,12 +825,11 @@ async fn edge_no_prefix_no_sanitizer() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let stored = files.lock().await;
+    let stored = objects(&files);
     assert_eq!(stored.len(), 1);
     assert_eq!(stored[0].0, "bare.html");
     assert_eq!(String::from_utf8_lossy(&stored[0].1), "<p>no prefix</p>");
 
-    drop(stored);
     handle.shutdown().await;
 }
 
@@ -731,7 +868,7 @@ async fn edge_save_or_log_does_not_panic() {
 
 #[tokio::test]
 async fn e2e_failing_storage_does_not_crash_worker() {
-    let handle = HtmlSaverBuilder::new(FailingStorage)
+    let handle = HtmlSaverBuilder::new(failing())
         .batch_size(1)
         .build::<SimpleDoc>();
 
@@ -802,3 +939,1699 @@ async fn e2e_large_batch_realistic_scenario() {
     assert!(!content.contains("BEARER_TOKEN_XYZ"));
     assert!(content.contains("[REDACTED]"));
 }
+
+// ---------------------------------------------------------------------------
+// Retry policy
+// ---------------------------------------------------------------------------
+
+fn fast_retry(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy::new()
+        .max_attempts(max_attempts)
+        .base_delay(Duration::from_millis(5))
+        .max_delay(Duration::from_millis(20))
+}
+
+#[tokio::test]
+async fn e2e_retry_flaky_storage_eventually_succeeds() {
+    let storage = FlakyStorage::new(2);
+    let files = storage.inner.clone();
+    let attempts = storage.attempts.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(5)
+        .flush_interval(Duration::from_secs(60))
+        .retry_policy(fast_retry(3))
+        .build::<SimpleDoc>();
+
+    for i in 0..5 {
+        handle
+            .save(SimpleDoc {
+                name: format!("flaky_{i}.html"),
+                html: format!("<p>{i}</p>"),
+            })
+            .unwrap();
+    }
+
+    handle.shutdown().await;
+
+    let stored = objects(&files);
+    assert_eq!(stored.len(), 5, "all items should land after retries");
+    let attempts = attempts.lock().await;
+    assert!(attempts.values().all(|&n| n == 3));
+}
+
+#[tokio::test]
+async fn e2e_retry_gives_up_after_max_attempts() {
+    let storage = FlakyStorage::new(5);
+    let files = storage.inner.clone();
+    let attempts = storage.attempts.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .retry_policy(fast_retry(3))
+        .build::<SimpleDoc>();
+
+    handle
+        .save(SimpleDoc {
+            name: "never.html".into(),
+            html: "<p>never</p>".into(),
+        })
+        .unwrap();
+
+    handle.shutdown().await;
+
+    assert!(files.is_empty());
+    assert_eq!(attempts.lock().await["never.html"], 3);
+}
+
+#[tokio::test]
+async fn e2e_retry_skips_non_retryable_errors() {
+    let storage = FlakyStorage::new(1);
+    let files = storage.inner.clone();
+    let attempts = storage.attempts.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .retry_policy(fast_retry(3).retry_if(|_| false))
+        .build::<SimpleDoc>();
+
+    handle
+        .save(SimpleDoc {
+            name: "once.html".into(),
+            html: "<p>once</p>".into(),
+        })
+        .unwrap();
+
+    handle.shutdown().await;
+
+    assert!(files.is_empty());
+    assert_eq!(attempts.lock().await["once.html"], 1);
+}
+
+#[tokio::test]
+async fn e2e_retry_recovers_from_injected_faults() {
+    let storage = FaultyStorage::new(MemoryStorage::new())
+        .fail_every(2)
+        .fail_key("poison.html");
+
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(1)
+        .max_concurrent_uploads(1)
+        .retry_policy(fast_retry(3))
+        .build::<SimpleDoc>();
+
+    let acks: Vec<_> = ["a.html", "b.html", "c.html", "poison.html"]
+        .into_iter()
+        .map(|name| handle.save_with_ack(doc(name)).unwrap())
+        .collect();
+    let mut results = Vec::new();
+    for ack in acks {
+        results.push(ack.await.is_ok());
+    }
+    handle.shutdown().await;
+
+    assert_eq!(results, [true, true, true, false]);
+    assert_eq!(storage.inner().keys(), ["a.html", "b.html", "c.html"]);
+    assert!(storage.injected_failures() >= 3);
+}
+
+// ---------------------------------------------------------------------------
+// Dead-letter sink
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn e2e_dead_letter_receives_failed_items() {
+    let spool = TempDir::new().unwrap();
+
+    let handle = HtmlSaverBuilder::new(failing())
+        .batch_size(1)
+        .prefix("prod")
+        .retry_policy(fast_retry(2))
+        .dead_letter(FsStorage::new(spool.path()))
+        .build::<SimpleDoc>();
+
+    handle
+        .save(SimpleDoc {
+            name: "lost.html".into(),
+            html: "<p>lost</p>".into(),
+        })
+        .unwrap();
+
+    handle.shutdown().await;
+
+    let content = tokio::fs::read_to_string(spool.path().join("prod/lost.html"))
+        .await
+        .unwrap();
+    assert_eq!(content, "<p>lost</p>");
+
+    let sidecar = tokio::fs::read(spool.path().join("prod/lost.html.deadletter.json"))
+        .await
+        .unwrap();
+    let record: DeadLetterRecord = serde_json::from_slice(&sidecar).unwrap();
+    assert_eq!(record.key, "prod/lost.html");
+    assert_eq!(record.attempts, 2);
+    assert!(record.error.contains("injected failure"));
+}
+
+#[tokio::test]
+async fn dead_letter_replay_uploads_and_cleans_spool() {
+    let spool = TempDir::new().unwrap();
+
+    let handle = HtmlSaverBuilder::new(failing())
+        .batch_size(2)
+        .dead_letter(FsStorage::new(spool.path()).write_metadata(true))
+        .build::<SimpleDoc>();
+
+    for name in ["a.html", "nested/b.html"] {
+        handle
+            .save(SimpleDoc {
+                name: name.into(),
+                html: format!("<p>{name}</p>"),
+            })
+            .unwrap();
+    }
+    handle.shutdown().await;
+    assert!(spool.path().join("a.html.meta.json").exists());
+
+    let primary = MemoryStorage::new();
+    let report = dead_letter::replay(spool.path(), &primary).await.unwrap();
+    assert_eq!(report.replayed, 2);
+    assert_eq!(report.failed, 0);
+
+    let mut stored: Vec<_> = objects(&primary)
+        .iter()
+        .map(|(k, v)| (k.clone(), String::from_utf8_lossy(v).into_owned()))
+        .collect();
+    stored.sort();
+    assert_eq!(
+        stored,
+        vec![
+            ("a.html".to_string(), "<p>a.html</p>".to_string()),
+            (
+                "nested/b.html".to_string(),
+                "<p>nested/b.html</p>".to_string()
+            ),
+        ]
+    );
+
+    assert_eq!(files_under(spool.path()), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn dead_letter_replay_skips_temp_files() {
+    let spool = TempDir::new().unwrap();
+    // Left behind by a dead-letter write that crashed.
+    let orphan = format!(".c.html.0-0{}", FsStorage::TEMP_SUFFIX);
+    std::fs::write(spool.path().join(&orphan), "<p>partial</p>").unwrap();
+
+    let primary = MemoryStorage::new();
+    let report = dead_letter::replay(spool.path(), &primary).await.unwrap();
+    assert_eq!(report.replayed, 0);
+    assert_eq!(report.failed, 0);
+    assert!(primary.keys().is_empty());
+    assert_eq!(files_under(spool.path()), vec![orphan]);
+}
+
+#[tokio::test]
+async fn dead_letter_replay_keeps_items_that_fail_again() {
+    let spool = TempDir::new().unwrap();
+    FsStorage::new(spool.path())
+        .put(
+            "stuck.html",
+            b"<p>stuck</p>",
+            &ObjectMetadata::new("text/html"),
+        )
+        .await
+        .unwrap();
+
+    let report = dead_letter::replay(spool.path(), &failing()).await.unwrap();
+    assert_eq!(report.replayed, 0);
+    assert_eq!(report.failed, 1);
+    assert!(spool.path().join("stuck.html").exists());
+}
+
+// ---------------------------------------------------------------------------
+// Write-ahead log
+// ---------------------------------------------------------------------------
+
+#[test]
+fn wal_items_survive_crash_and_are_replayed() {
+    let wal_dir = TempDir::new().unwrap();
+
+    // First run: queue items, then kill the runtime before anything is flushed.
+    let crashed = tokio::runtime::Runtime::new().unwrap();
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    crashed.block_on(async {
+        let handle = HtmlSaverBuilder::new(storage)
+            .batch_size(100)
+            .flush_interval(Duration::from_secs(60))
+            .write_ahead_log(WalConfig::new(wal_dir.path()))
+            .build::<SimpleDoc>();
+        for i in 0..3 {
+            handle
+                .save(SimpleDoc {
+                    name: format!("wal_{i}.html"),
+                    html: format!("<p>{i}</p>"),
+                })
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::mem::forget(handle);
+    });
+    crashed.shutdown_background();
+    assert!(files.is_empty());
+
+    // Second run: the items are replayed from the log.
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    rt.block_on(async {
+        let handle = HtmlSaverBuilder::new(storage)
+            .write_ahead_log(WalConfig::new(wal_dir.path()))
+            .build::<SimpleDoc>();
+        handle.shutdown().await;
+    });
+
+    let mut names: Vec<_> = objects(&files).iter().map(|(k, _)| k.clone()).collect();
+    names.sort();
+    assert_eq!(names, vec!["wal_0.html", "wal_1.html", "wal_2.html"]);
+}
+
+#[tokio::test]
+async fn wal_acknowledged_items_are_not_replayed() {
+    let wal_dir = TempDir::new().unwrap();
+
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle
+        .save(SimpleDoc {
+            name: "once.html".into(),
+            html: "<p>once</p>".into(),
+        })
+        .unwrap();
+    handle.shutdown().await;
+
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    let handle = HtmlSaverBuilder::new(storage)
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle.shutdown().await;
+
+    assert!(files.is_empty());
+}
+
+#[tokio::test]
+async fn wal_failed_items_are_kept_for_next_run() {
+    let wal_dir = TempDir::new().unwrap();
+
+    let handle = HtmlSaverBuilder::new(failing())
+        .batch_size(1)
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle
+        .save(SimpleDoc {
+            name: "retry_later.html".into(),
+            html: "<p>later</p>".into(),
+        })
+        .unwrap();
+    handle.shutdown().await;
+
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    let handle = HtmlSaverBuilder::new(storage)
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle.shutdown().await;
+
+    let stored = objects(&files);
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].0, "retry_later.html");
+}
+
+#[tokio::test]
+async fn wal_items_failing_with_non_retryable_errors_are_not_replayed() {
+    let wal_dir = TempDir::new().unwrap();
+
+    let handle = HtmlSaverBuilder::new(failing())
+        .batch_size(1)
+        .retry_policy(RetryPolicy::none().retry_if(|_| false))
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle
+        .save(SimpleDoc {
+            name: "forbidden.html".into(),
+            html: "<p>forbidden</p>".into(),
+        })
+        .unwrap();
+    handle.shutdown().await;
+
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .write_ahead_log(WalConfig::new(wal_dir.path()))
+        .build::<SimpleDoc>();
+    handle.shutdown().await;
+
+    assert!(storage.is_empty());
+}
+
+// ---------------------------------------------------------------------------
+// Backpressure-aware saving
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn save_async_waits_for_capacity() {
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .channel_buffer(1)
+        .build::<SimpleDoc>();
+
+    // Far more items than the channel can hold at once; none may be dropped.
+    for i in 0..50 {
+        handle
+            .save_async(SimpleDoc {
+                name: format!("burst_{i}.html"),
+                html: format!("<p>{i}</p>"),
+            })
+            .await
+            .unwrap();
+    }
+
+    let sender = handle.sender();
+    sender
+        .save_async(SimpleDoc {
+            name: "from_sender.html".into(),
+            html: "<p>sender</p>".into(),
+        })
+        .await
+        .unwrap();
+
+    handle.shutdown().await;
+    assert_eq!(files.len(), 51);
+}
+
+#[tokio::test]
+async fn save_timeout_fails_when_channel_stays_full() {
+    let handle = HtmlSaverBuilder::new(StalledStorage)
+        .batch_size(1)
+        .max_concurrent_uploads(1)
+        .sanitize_buffer(1)
+        .upload_buffer(1)
+        .channel_buffer(1)
+        .build::<SimpleDoc>();
+
+    let doc = |name: &str| SimpleDoc {
+        name: name.into(),
+        html: String::new(),
+    };
+
+    // The worker stalls uploading the first item and each of its stages
+    // holds one of the next four; the sixth one fills the channel.
+    for i in 1..=6 {
+        handle.save(doc(&format!("{i}.html"))).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let result = handle
+        .save_timeout(doc("7.html"), Duration::from_millis(50))
+        .await;
+    assert!(matches!(result, Err(SaveError::Full(doc)) if doc.name == "7.html"));
+}
+
+#[tokio::test]
+async fn save_timeout_succeeds_once_capacity_frees_up() {
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .channel_buffer(1)
+        .build::<SimpleDoc>();
+
+    for i in 0..10 {
+        handle
+            .save_timeout(
+                SimpleDoc {
+                    name: format!("t_{i}.html"),
+                    html: String::new(),
+                },
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+    }
+
+    handle.shutdown().await;
+    assert_eq!(files.len(), 10);
+}
+
+#[tokio::test]
+async fn save_after_worker_stopped_reports_closed() {
+    let handle = HtmlSaverBuilder::new(MemoryStorage::new()).build::<SimpleDoc>();
+    let sender = handle.sender();
+    handle.shutdown().await;
+
+    let doc = SimpleDoc {
+        name: "late.html".into(),
+        html: "<p>late</p>".into(),
+    };
+    let err = sender.save(doc).unwrap_err();
+    assert!(err.is_closed());
+    assert_eq!(err.into_inner().html, "<p>late</p>");
+
+    let err = sender
+        .save_async(SimpleDoc {
+            name: "late2.html".into(),
+            html: String::new(),
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SaveError::Closed(_)));
+    assert!(matches!(
+        HtmlSaverError::from(err),
+        HtmlSaverError::ChannelClosed
+    ));
+}
+
+// ---------------------------------------------------------------------------
+// Per-item acknowledgements
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn save_with_ack_resolves_with_key_after_upload() {
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .prefix("acked")
+        .build::<SimpleDoc>();
+
+    let ack = handle
+        .save_with_ack(SimpleDoc {
+            name: "page.html".into(),
+            html: "<p>ack</p>".into(),
+        })
+        .unwrap();
+
+    let key = ack.await.unwrap();
+    assert_eq!(key, "acked/page.html");
+    // The object exists by the time the acknowledgement resolves.
+    assert!(objects(&files).iter().any(|(k, _)| k == &key));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn save_with_ack_reports_upload_failure() {
+    let handle = HtmlSaverBuilder::new(failing())
+        .batch_size(1)
+        .build::<SimpleDoc>();
+
+    let ack = handle
+        .sender()
+        .save_with_ack(SimpleDoc {
+            name: "fail.html".into(),
+            html: String::new(),
+        })
+        .unwrap();
+
+    assert!(matches!(ack.await, Err(HtmlSaverError::StorageUpload(_))));
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn save_with_ack_resolves_on_shutdown_drain() {
+    let handle = HtmlSaverBuilder::new(MemoryStorage::new())
+        .batch_size(100)
+        .flush_interval(Duration::from_secs(60))
+        .build::<SimpleDoc>();
+
+    let acks: Vec<_> = (0..3)
+        .map(|i| {
+            handle
+                .save_with_ack(SimpleDoc {
+                    name: format!("drain_{i}.html"),
+                    html: String::new(),
+                })
+                .unwrap()
+        })
+        .collect();
+
+    handle.shutdown().await;
+
+    let keys: Vec<_> = futures::future::join_all(acks)
+        .await
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(keys, vec!["drain_0.html", "drain_1.html", "drain_2.html"]);
+}
+
+// ---------------------------------------------------------------------------
+// Key validation
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn traversal_names_are_rejected_by_default() {
+    let tmp = TempDir::new().unwrap();
+    let base = tmp.path().join("base");
+    let handle = HtmlSaverBuilder::new(FsStorage::new(&base))
+        .batch_size(1)
+        .build::<SimpleDoc>();
+
+    let rejected = handle.save_with_ack(doc("../../escaped.html")).unwrap();
+    let accepted = handle.save_with_ack(doc("ok.html")).unwrap();
+
+    assert!(matches!(
+        rejected.await,
+        Err(HtmlSaverError::InvalidKey { key, .. }) if key == "../../escaped.html"
+    ));
+    assert_eq!(accepted.await.unwrap(), "ok.html");
+    handle.shutdown().await;
+    assert!(!tmp.path().join("escaped.html").exists());
+}
+
+#[tokio::test]
+async fn normalize_policy_rewrites_keys() {
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .prefix("pages")
+        .key_policy(KeyPolicy::Normalize)
+        .build::<SimpleDoc>();
+
+    let key = handle
+        .save_with_ack(doc("/../../a//b\\c\0.html"))
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(key, "pages/a/b/c_.html");
+    handle.shutdown().await;
+    assert_eq!(objects(&files)[0].0, "pages/a/b/c_.html");
+}
+
+#[tokio::test]
+async fn invalid_prefix_fails_the_build() {
+    let result = HtmlSaverBuilder::new(MemoryStorage::new())
+        .prefix("../outside")
+        .try_build::<SimpleDoc>();
+    assert!(matches!(result, Err(HtmlSaverError::Config(_))));
+}
+
+// ---------------------------------------------------------------------------
+// Key templates
+// ---------------------------------------------------------------------------
+
+/// A page whose host is available to key templates as `{host}`.
+struct HostedPage {
+    host: Option<String>,
+    path: String,
+    html: String,
+}
+
+impl Saveable for HostedPage {
+    fn content(&self) -> &str {
+        &self.html
+    }
+
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    fn key_field(&self, field: &str) -> Option<String> {
+        match field {
+            "host" => self.host.clone(),
+            _ => None,
+        }
+    }
+}
+
+fn hosted(host: Option<&str>, path: &str) -> HostedPage {
+    HostedPage {
+        host: host.map(String::from),
+        path: path.into(),
+        html: "<p>hi</p>".into(),
+    }
+}
+
+#[tokio::test]
+async fn key_template_builds_partitioned_keys() {
+    let storage = MemoryStorage::new();
+    let files = storage.clone();
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .prefix("crawl")
+        .key_template("{date:%Y}/{host}/{sha256:8}-{name}")
+        .build::<HostedPage>();
+
+    let key = handle
+        .save_with_ack(hosted(Some("example.com"), "index.html"))
+        .unwrap()
+        .await
+        .unwrap();
+    handle.shutdown().await;
+
+    let segments: Vec<&str> = key.split('/').collect();
+    assert_eq!(segments[0], "crawl");
+    assert!(segments[1].len() == 4 && segments[1].chars().all(|c| c.is_ascii_digit()));
+    assert_eq!(segments[2..], ["example.com", "0a473528-index.html"]);
+    assert_eq!(objects(&files)[0].0, key);
+}
+
+#[tokio::test]
+async fn key_template_rejects_items_missing_a_field() {
+    let handle = HtmlSaverBuilder::new(MemoryStorage::new())
+        .key_template("{host}/{name}")
+        .build::<HostedPage>();
+
+    let err = handle.save(hosted(None, "index.html")).unwrap_err();
+    assert!(matches!(
+        &err,
+        SaveError::Key(_, HtmlSaverError::InvalidKey { key, .. }) if key == "{host}"
+    ));
+    assert_eq!(err.into_inner().path, "index.html");
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn invalid_key_template_fails_the_build() {
+    let result = HtmlSaverBuilder::new(MemoryStorage::new())
+        .key_template("{date:%Q}/{name}")
+        .try_build::<HostedPage>();
+    assert!(matches!(result, Err(HtmlSaverError::Config(msg)) if msg.contains("%Q")));
+}
+
+// ---------------------------------------------------------------------------
+// Overwrite policies
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn colliding_names_get_suffixed_keys() {
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()))
+        .batch_size(1)
+        .max_concurrent_uploads(1)
+        .overwrite_policy(OverwritePolicy::Suffix(KeySuffix::Counter))
+        .build::<SimpleDoc>();
+
+    let mut keys = Vec::new();
+    for html in ["<p>1</p>", "<p>2</p>", "<p>3</p>"] {
+        let ack = handle
+            .save_with_ack(SimpleDoc {
+                name: "page.html".into(),
+                html: html.into(),
+            })
+            .unwrap();
+        keys.push(ack.await.unwrap());
+    }
+    handle.shutdown().await;
+
+    assert_eq!(keys, ["page.html", "page-1.html", "page-2.html"]);
+    let stored = std::fs::read_to_string(tmp.path().join("page-2.html")).unwrap();
+    assert_eq!(stored, "<p>3</p>");
+}
+
+#[tokio::test]
+async fn skip_if_exists_keeps_the_first_item() {
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()))
+        .batch_size(1)
+        .max_concurrent_uploads(1)
+        .overwrite_policy(OverwritePolicy::SkipIfExists)
+        .build::<SimpleDoc>();
+
+    for html in ["<p>first</p>", "<p>second</p>"] {
+        let ack = handle
+            .save_with_ack(SimpleDoc {
+                name: "page.html".into(),
+                html: html.into(),
+            })
+            .unwrap();
+        assert_eq!(ack.await.unwrap(), "page.html");
+    }
+    handle.shutdown().await;
+
+    let stored = std::fs::read_to_string(tmp.path().join("page.html")).unwrap();
+    assert_eq!(stored, "<p>first</p>");
+}
+
+// ---------------------------------------------------------------------------
+// Deduplication
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn dedup_stores_identical_pages_once() {
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()))
+        .batch_size(1)
+        .deduplicate(DedupConfig::new())
+        .build::<SimpleDoc>();
+
+    for (name, html) in [
+        ("a.html", "<p>same</p>"),
+        ("b.html", "<p>same</p>"),
+        ("c.html", "<p>other</p>"),
+    ] {
+        let ack = handle
+            .save_with_ack(SimpleDoc {
+                name: name.into(),
+                html: html.into(),
+            })
+            .unwrap();
+        assert_eq!(ack.await.unwrap(), name);
+    }
+    handle.shutdown().await;
+
+    let blobs: Vec<_> = std::fs::read_dir(tmp.path().join("blobs"))
+        .unwrap()
+        .flat_map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap())
+        .collect();
+    assert_eq!(blobs.len(), 2);
+
+    let manifest: Manifest =
+        serde_json::from_slice(&std::fs::read(tmp.path().join("b.html")).unwrap()).unwrap();
+    assert!(manifest.blob.starts_with("blobs/"));
+    assert_eq!(manifest.content_type, "text/html");
+
+    let object = dedup::read(&FsStorage::new(tmp.path()), "b.html")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(object.content, b"<p>same</p>");
+}
+
+#[tokio::test]
+async fn invalid_blob_prefix_fails_the_build() {
+    let result = HtmlSaverBuilder::new(MemoryStorage::new())
+        .deduplicate(DedupConfig::new().blob_prefix("/blobs"))
+        .try_build::<SimpleDoc>();
+    assert!(matches!(result, Err(HtmlSaverError::Config(_))));
+}
+
+// ---------------------------------------------------------------------------
+// Overflow policies
+// ---------------------------------------------------------------------------
+
+fn doc(name: &str) -> SimpleDoc {
+    SimpleDoc {
+        name: name.into(),
+        html: format!("<p>{name}</p>"),
+    }
+}
+
+/// Items held inside the worker of a [`saturated`] saver: one in each of the
+/// upload buffer, the sanitize stage, the sanitize buffer and the batching
+/// stage.
+const WAITING: [&str; 4] = [
+    "waiting_0.html",
+    "waiting_1.html",
+    "waiting_2.html",
+    "waiting_3.html",
+];
+
+/// Build a saver whose only upload slot is stuck on `first.html`, whose
+/// worker stages all hold one of [`WAITING`], and whose single channel slot
+/// holds `queued.html`.
+async fn saturated(
+    storage: impl Storage,
+    policy: OverflowPolicy,
+) -> html_saver::HtmlSaverHandle<SimpleDoc> {
+    let handle = HtmlSaverBuilder::new(storage)
+        .batch_size(1)
+        .max_concurrent_uploads(1)
+        .sanitize_buffer(1)
+        .upload_buffer(1)
+        .flush_interval(Duration::from_millis(20))
+        .channel_buffer(1)
+        .overflow_policy(policy)
+        .build::<SimpleDoc>();
+    let names = std::iter::once("first.html")
+        .chain(WAITING)
+        .chain(["queued.html"]);
+    for name in names {
+        handle.save(doc(name)).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    handle
+}
+
+/// `names` plus [`WAITING`], sorted like [`stored_names`].
+fn with_waiting(names: &[&str]) -> Vec<String> {
+    let mut all: Vec<_> = names
+        .iter()
+        .chain(&WAITING)
+        .map(|n| n.to_string())
+        .collect();
+    all.sort();
+    all
+}
+
+async fn stored_names(storage: &MemoryStorage) -> Vec<String> {
+    let mut names: Vec<_> = objects(storage).iter().map(|(k, _)| k.clone()).collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn overflow_reject_is_counted() {
+    let storage = GatedStorage::new();
+    let handle = saturated(storage.clone(), OverflowPolicy::Reject).await;
+
+    let err = handle.save(doc("rejected.html")).unwrap_err();
+    assert!(err.is_full());
+    assert_eq!(handle.overflow_stats().rejected, 1);
+
+    storage.gate.add_permits(10);
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn overflow_drop_newest_discards_new_item() {
+    let storage = GatedStorage::new();
+    let handle = saturated(storage.clone(), OverflowPolicy::DropNewest).await;
+
+    handle.save(doc("dropped.html")).unwrap();
+    let ack = handle.sender().save_with_ack(doc("dropped2.html")).unwrap();
+    assert!(matches!(ack.await, Err(HtmlSaverError::Dropped)));
+
+    let stats = handle.overflow_stats();
+    assert_eq!(stats.dropped_newest, 2);
+    assert_eq!(stats.total(), 2);
+
+    storage.gate.add_permits(10);
+    handle.shutdown().await;
+    assert_eq!(
+        stored_names(&storage.inner).await,
+        with_waiting(&["first.html", "queued.html"])
+    );
+}
+
+#[tokio::test]
+async fn overflow_drop_oldest_makes_room() {
+    let storage = GatedStorage::new();
+    let handle = saturated(storage.clone(), OverflowPolicy::DropOldest).await;
+
+    handle.sender().save(doc("newest.html")).unwrap();
+    assert_eq!(handle.overflow_stats().dropped_oldest, 1);
+
+    storage.gate.add_permits(10);
+    handle.shutdown().await;
+    assert_eq!(
+        stored_names(&storage.inner).await,
+        with_waiting(&["first.html", "newest.html"])
+    );
+}
+
+#[tokio::test]
+async fn overflow_spill_to_disk_is_drained_by_worker() {
+    let spill = TempDir::new().unwrap();
+    let storage = GatedStorage::new();
+    let handle = saturated(
+        storage.clone(),
+        OverflowPolicy::SpillToDisk(spill.path().to_path_buf()),
+    )
+    .await;
+
+    let ack = handle.save_with_ack(doc("spilled.html")).unwrap();
+    assert_eq!(handle.overflow_stats().spilled, 1);
+    assert_eq!(std::fs::read_dir(spill.path()).unwrap().count(), 1);
+
+    storage.gate.add_permits(10);
+    assert_eq!(ack.await.unwrap(), "spilled.html");
+
+    handle.shutdown().await;
+    assert_eq!(
+        stored_names(&storage.inner).await,
+        with_waiting(&["first.html", "queued.html", "spilled.html"])
+    );
+    assert_eq!(std::fs::read_dir(spill.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn overflow_spilled_items_survive_failed_uploads() {
+    let spill = TempDir::new().unwrap();
+    let gated = GatedStorage::new();
+    let storage = FaultyStorage::new(gated.clone()).fail_key("spilled.html");
+    let handle = saturated(
+        storage,
+        OverflowPolicy::SpillToDisk(spill.path().to_path_buf()),
+    )
+    .await;
+
+    let ack = handle.save_with_ack(doc("spilled.html")).unwrap();
+    assert_eq!(handle.overflow_stats().spilled, 1);
+
+    gated.gate.add_permits(10);
+    assert!(matches!(ack.await, Err(HtmlSaverError::StorageUpload(_))));
+    handle.shutdown().await;
+    assert_eq!(std::fs::read_dir(spill.path()).unwrap().count(), 1);
+
+    // The next run picks the item up again.
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .overflow_policy(OverflowPolicy::SpillToDisk(spill.path().to_path_buf()))
+        .build::<SimpleDoc>();
+    handle.shutdown().await;
+    assert_eq!(storage.keys(), ["spilled.html"]);
+    assert_eq!(std::fs::read_dir(spill.path()).unwrap().count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn overflow_block_waits_for_capacity() {
+    let storage = GatedStorage::new();
+    let handle = saturated(storage.clone(), OverflowPolicy::Block).await;
+
+    let gate = storage.gate.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gate.add_permits(10);
+    });
+
+    handle.save(doc("blocked.html")).unwrap();
+    assert_eq!(handle.overflow_stats().total(), 0);
+
+    handle.shutdown().await;
+    assert_eq!(
+        stored_names(&storage.inner).await,
+        with_waiting(&["blocked.html", "first.html", "queued.html"])
+    );
+}
+
+#[tokio::test]
+async fn overflow_block_fails_the_build_on_current_thread_runtime() {
+    let result = HtmlSaverBuilder::new(MemoryStorage::new())
+        .overflow_policy(OverflowPolicy::Block)
+        .try_build::<SimpleDoc>();
+    assert!(matches!(result, Err(HtmlSaverError::Config(_))));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn overflow_block_rejects_from_a_current_thread_runtime() {
+    let storage = GatedStorage::new();
+    let handle = saturated(storage.clone(), OverflowPolicy::Block).await;
+
+    // Saving from a current-thread runtime nested in another thread.
+    let sender = handle.sender();
+    let err = std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async { sender.save(doc("blocked.html")).unwrap_err() })
+    })
+    .join()
+    .unwrap();
+    assert!(err.is_full());
+    assert_eq!(handle.overflow_stats().rejected, 1);
+
+    storage.gate.add_permits(10);
+    handle.shutdown().await;
+}
+
+// ---------------------------------------------------------------------------
+// Upload concurrency
+// ---------------------------------------------------------------------------
+
+/// Storage that records the highest number of puts running at the same time.
+#[derive(Clone)]
+struct ConcurrencyProbe {
+    inner: MemoryStorage,
+    running: Arc<std::sync::atomic::AtomicUsize>,
+    peak: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl ConcurrencyProbe {
+    fn new() -> Self {
+        Self {
+            inner: MemoryStorage::new(),
+            running: Arc::default(),
+            peak: Arc::default(),
+        }
+    }
+}
+
+impl Storage for ConcurrencyProbe {
+    async fn put(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> html_saver::Result<()> {
+        use std::sync::atomic::Ordering;
+        let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        self.inner.put(key, content, metadata).await
+    }
+}
+
+#[tokio::test]
+async fn uploads_are_limited_to_max_concurrent_uploads() {
+    let storage = ConcurrencyProbe::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(50)
+        .max_concurrent_uploads(4)
+        .flush_interval(Duration::from_secs(60))
+        .build::<SimpleDoc>();
+
+    for i in 0..50 {
+        handle.save(doc(&format!("page_{i}.html"))).unwrap();
+    }
+    handle.shutdown().await;
+
+    assert_eq!(storage.inner.len(), 50);
+    assert_eq!(storage.peak.load(std::sync::atomic::Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn worker_keeps_receiving_while_uploads_are_in_flight() {
+    let storage = GatedStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(1)
+        .max_concurrent_uploads(8)
+        .channel_buffer(1)
+        .build::<SimpleDoc>();
+
+    // Every upload is stuck, yet the channel keeps draining into free slots.
+    for i in 0..5 {
+        handle.save(doc(&format!("page_{i}.html"))).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    storage.gate.add_permits(10);
+    handle.shutdown().await;
+    assert_eq!(stored_names(&storage.inner).await.len(), 5);
+}
+
+// ---------------------------------------------------------------------------
+// Pipelined worker
+// ---------------------------------------------------------------------------
+
+/// Sanitizer that holds every document until it is opened, standing in for
+/// selector rules on large pages.
+#[derive(Clone, Default)]
+struct GatedSanitizer(Arc<std::sync::atomic::AtomicBool>);
+
+impl GatedSanitizer {
+    fn open(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl Sanitizer for GatedSanitizer {
+    fn sanitize(&self, html: &str) -> String {
+        while !self.0.load(std::sync::atomic::Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        html.to_uppercase()
+    }
+}
+
+#[tokio::test]
+async fn slow_sanitizers_do_not_stall_the_channel() {
+    let sanitizer = GatedSanitizer::default();
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(1)
+        .channel_buffer(1)
+        .sanitizer_parallelism(1)
+        .add_sanitizer(sanitizer.clone())
+        .build::<SimpleDoc>();
+
+    // The sanitize buffer absorbs items while the sanitizer is busy.
+    let timeout = Duration::from_secs(1);
+    let mut saved = 0;
+    for i in 0..10 {
+        let page = doc(&format!("page_{i}.html"));
+        if handle.save_timeout(page, timeout).await.is_ok() {
+            saved += 1;
+        }
+    }
+
+    sanitizer.open();
+    assert_eq!(saved, 10);
+    handle.shutdown().await;
+    let files = objects(&storage);
+    assert_eq!(files.len(), 10);
+    assert!(files.iter().all(|(_, c)| c.starts_with(b"<P>")));
+}
+
+// ---------------------------------------------------------------------------
+// Sanitizer executors
+// ---------------------------------------------------------------------------
+
+/// Sanitizer that records the threads it runs on.
+#[derive(Clone, Default)]
+struct ThreadRecordingSanitizer(Arc<std::sync::Mutex<Vec<std::thread::ThreadId>>>);
+
+impl Sanitizer for ThreadRecordingSanitizer {
+    fn sanitize(&self, html: &str) -> String {
+        self.0.lock().unwrap().push(std::thread::current().id());
+        html.to_string()
+    }
+}
+
+/// Sanitize a few items with `executor` on the current-thread test runtime and
+/// return whether each ran on the runtime's thread.
+async fn sanitized_on_runtime_thread(executor: SanitizerExecutor) -> Vec<bool> {
+    let sanitizer = ThreadRecordingSanitizer::default();
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(1)
+        .sanitizer_executor(executor)
+        .sanitizer_parallelism(2)
+        .add_sanitizer(sanitizer.clone())
+        .build::<SimpleDoc>();
+    for i in 0..4 {
+        handle.save(doc(&format!("page_{i}.html"))).unwrap();
+    }
+    handle.shutdown().await;
+    assert_eq!(storage.len(), 4);
+
+    let runtime_thread = std::thread::current().id();
+    let threads = sanitizer.0.lock().unwrap();
+    threads.iter().map(|&id| id == runtime_thread).collect()
+}
+
+#[tokio::test]
+async fn inline_sanitizers_run_on_the_runtime_thread() {
+    let on_runtime = sanitized_on_runtime_thread(SanitizerExecutor::Inline).await;
+    assert_eq!(on_runtime, [true; 4]);
+}
+
+#[tokio::test]
+async fn blocking_pool_sanitizers_run_off_the_runtime_thread() {
+    let on_runtime = sanitized_on_runtime_thread(SanitizerExecutor::Blocking).await;
+    assert_eq!(on_runtime, [false; 4]);
+}
+
+#[tokio::test]
+async fn dedicated_thread_sanitizers_run_off_the_runtime_thread() {
+    let on_runtime = sanitized_on_runtime_thread(SanitizerExecutor::Dedicated).await;
+    assert_eq!(on_runtime, [false; 4]);
+}
+
+/// Sanitizer that keeps its thread busy, standing in for CPU-heavy rules.
+struct BusySanitizer(Duration);
+
+impl Sanitizer for BusySanitizer {
+    fn sanitize(&self, html: &str) -> String {
+        let start = std::time::Instant::now();
+        while start.elapsed() < self.0 {
+            std::hint::spin_loop();
+        }
+        html.to_string()
+    }
+}
+
+const BUSY: Duration = Duration::from_millis(300);
+
+/// Sanitize a few items with `executor` on the current-thread test runtime and
+/// return the longest delay seen by a task that wakes up every 5 ms meanwhile.
+async fn worst_wakeup_delay(executor: SanitizerExecutor) -> Duration {
+    let storage = MemoryStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .batch_size(1)
+        .sanitizer_executor(executor)
+        .sanitizer_parallelism(2)
+        .add_sanitizer(BusySanitizer(BUSY))
+        .build::<SimpleDoc>();
+    for i in 0..4 {
+        handle.save(doc(&format!("page_{i}.html"))).unwrap();
+    }
+
+    let mut worst = Duration::ZERO;
+    for _ in 0..30 {
+        let start = std::time::Instant::now();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        worst = worst.max(start.elapsed());
+    }
+
+    handle.shutdown().await;
+    assert_eq!(storage.len(), 4);
+    worst
+}
+
+#[tokio::test]
+async fn inline_sanitizers_block_the_runtime() {
+    let worst = worst_wakeup_delay(SanitizerExecutor::Inline).await;
+    assert!(worst >= BUSY, "worst delay {worst:?}");
+}
+
+// The bound is half the busy time so that only a sanitizer blocking the
+// runtime, not a slow CI machine, fails these.
+#[tokio::test]
+async fn blocking_pool_sanitizers_keep_the_runtime_responsive() {
+    let worst = worst_wakeup_delay(SanitizerExecutor::Blocking).await;
+    assert!(worst < BUSY / 2, "worst delay {worst:?}");
+}
+
+#[tokio::test]
+async fn dedicated_thread_sanitizers_keep_the_runtime_responsive() {
+    let worst = worst_wakeup_delay(SanitizerExecutor::Dedicated).await;
+    assert!(worst < BUSY / 2, "worst delay {worst:?}");
+}
+
+/// Sanitizer that panics on every document.
+struct PanickingSanitizer;
+
+impl Sanitizer for PanickingSanitizer {
+    fn sanitize(&self, _html: &str) -> String {
+        panic!("poison document");
+    }
+}
+
+#[tokio::test]
+async fn panicking_sanitizers_fail_the_item_and_acknowledge_it() {
+    for executor in [
+        SanitizerExecutor::Inline,
+        SanitizerExecutor::Blocking,
+        SanitizerExecutor::Dedicated,
+    ] {
+        let wal_dir = TempDir::new().unwrap();
+        let handle = HtmlSaverBuilder::new(MemoryStorage::new())
+            .batch_size(1)
+            .sanitizer_executor(executor)
+            .add_sanitizer(PanickingSanitizer)
+            .write_ahead_log(WalConfig::new(wal_dir.path()))
+            .build::<SimpleDoc>();
+        let ack = handle.save_with_ack(doc("poison.html")).unwrap();
+        assert!(matches!(ack.await, Err(HtmlSaverError::Sanitizer(_))));
+        handle.shutdown().await;
+
+        // The poison item is not replayed on the next start.
+        let storage = MemoryStorage::new();
+        let handle = HtmlSaverBuilder::new(storage.clone())
+            .write_ahead_log(WalConfig::new(wal_dir.path()))
+            .build::<SimpleDoc>();
+        handle.shutdown().await;
+        assert!(storage.is_empty());
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Compression
+// ---------------------------------------------------------------------------
+
+/// Storage that records the metadata of every put.
+#[derive(Clone)]
+struct MetadataStorage {
+    inner: MemoryStorage,
+    metadata: Arc<TokioMutex<Vec<(String, ObjectMetadata)>>>,
+}
+
+impl MetadataStorage {
+    fn new() -> Self {
+        Self {
+            inner: MemoryStorage::new(),
+            metadata: Arc::new(TokioMutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Storage for MetadataStorage {
+    async fn put(
+        &self,
+        key: &str,
+        content: &[u8],
+        metadata: &ObjectMetadata,
+    ) -> html_saver::Result<()> {
+        self.metadata
+            .lock()
+            .await
+            .push((key.to_string(), metadata.clone()));
+        self.inner.put(key, content, metadata).await
+    }
+}
+
+#[tokio::test]
+async fn uncompressed_items_carry_no_content_encoding() {
+    let storage = MetadataStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone()).build::<SimpleDoc>();
+    handle.save(doc("plain.html")).unwrap();
+    handle.shutdown().await;
+
+    let metadata = storage.metadata.lock().await;
+    assert_eq!(
+        *metadata,
+        vec![("plain.html".to_string(), ObjectMetadata::new("text/html"))]
+    );
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn gzip_compressed_files_are_written_with_suffix() {
+    use std::io::Read;
+
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()))
+        .prefix("pages")
+        .compression(html_saver::Compression::gzip())
+        .build::<SimpleDoc>();
+    let ack = handle.save_with_ack(doc("index.html")).unwrap();
+    handle.shutdown().await;
+    assert_eq!(ack.await.unwrap(), "pages/index.html.gz");
+
+    let compressed = std::fs::read(tmp.path().join("pages/index.html.gz")).unwrap();
+    let mut html = String::new();
+    flate2::read::GzDecoder::new(&compressed[..])
+        .read_to_string(&mut html)
+        .unwrap();
+    assert_eq!(html, "<p>index.html</p>");
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn zstd_compressed_items_carry_content_encoding() {
+    let storage = MetadataStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .compression(html_saver::Compression::zstd())
+        .build::<SimpleDoc>();
+    handle.save(doc("index.html")).unwrap();
+    handle.shutdown().await;
+
+    let (key, metadata) = storage.metadata.lock().await[0].clone();
+    assert_eq!(key, "index.html.zst");
+    assert_eq!(metadata.content_encoding.as_deref(), Some("zstd"));
+    assert_eq!(metadata.content_type, "text/html");
+}
+
+// ---------------------------------------------------------------------------
+// Object metadata
+// ---------------------------------------------------------------------------
+
+/// Saveable that describes where and when the page was scraped.
+struct ScrapedPage {
+    url: String,
+    status: u16,
+    html: String,
+}
+
+impl Saveable for ScrapedPage {
+    fn content(&self) -> &str {
+        &self.html
+    }
+
+    fn name(&self) -> String {
+        "page.html".into()
+    }
+
+    fn metadata(&self) -> ObjectMetadata {
+        ObjectMetadata::new("text/html")
+            .cache_control("no-cache")
+            .user_metadata("source-url", &self.url)
+            .user_metadata("http-status", self.status.to_string())
+            .tag("crawl", "daily")
+    }
+}
+
+fn scraped_page() -> ScrapedPage {
+    ScrapedPage {
+        url: "https://example.com/".into(),
+        status: 200,
+        html: "<p>hi</p>".into(),
+    }
+}
+
+#[tokio::test]
+async fn saveable_metadata_reaches_storage() {
+    let storage = MetadataStorage::new();
+    let handle = HtmlSaverBuilder::new(storage.clone()).build::<ScrapedPage>();
+    handle.save(scraped_page()).unwrap();
+    handle.shutdown().await;
+
+    let (_, metadata) = storage.metadata.lock().await[0].clone();
+    assert_eq!(metadata, scraped_page().metadata());
+}
+
+#[tokio::test]
+async fn saveable_metadata_survives_wal_replay() {
+    let wal = TempDir::new().unwrap();
+    let storage = MetadataStorage::new();
+
+    // Nothing is uploaded by a stalled worker, so the item stays in the WAL.
+    let handle = HtmlSaverBuilder::new(StalledStorage)
+        .write_ahead_log(WalConfig::new(wal.path()))
+        .build::<ScrapedPage>();
+    handle.save(scraped_page()).unwrap();
+    drop(handle);
+
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .write_ahead_log(WalConfig::new(wal.path()))
+        .build::<ScrapedPage>();
+    handle.shutdown().await;
+
+    let (_, metadata) = storage.metadata.lock().await[0].clone();
+    assert_eq!(metadata, scraped_page().metadata());
+}
+
+#[tokio::test]
+async fn fs_storage_writes_metadata_sidecar() {
+    let tmp = TempDir::new().unwrap();
+    let handle = HtmlSaverBuilder::new(FsStorage::new(tmp.path()).write_metadata(true))
+        .build::<ScrapedPage>();
+    handle.save(scraped_page()).unwrap();
+    handle.shutdown().await;
+
+    let sidecar = std::fs::read(tmp.path().join("page.html.meta.json")).unwrap();
+    let metadata: ObjectMetadata = serde_json::from_slice(&sidecar).unwrap();
+    assert_eq!(metadata, scraped_page().metadata());
+    assert_eq!(
+        std::fs::read_to_string(tmp.path().join("page.html")).unwrap(),
+        "<p>hi</p>"
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Content types
+// ---------------------------------------------------------------------------
+
+/// Saveable carrying arbitrary bytes of a given MIME type.
+struct Payload {
+    name: &'static str,
+    content_type: &'static str,
+    data: Vec<u8>,
+}
+
+impl Saveable for Payload {
+    fn content(&self) -> &str {
+        ""
+    }
+
+    fn content_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn content_type(&self) -> &str {
+        self.content_type
+    }
+
+    fn name(&self) -> String {
+        self.name.into()
+    }
+}
+
+/// Strips `<script>` elements and masks the word "secret".
+fn script_and_secret_sanitizers(
+    builder: HtmlSaverBuilder<MetadataStorage>,
+) -> HtmlSaverBuilder<MetadataStorage> {
+    builder
+        .add_sanitizer(SelectorSanitizer::new(vec![(
+            "script",
+            SelectorAction::RemoveElement,
+        )]))
+        .add_sanitizer(SubstringSanitizer::new(vec![("secret", "***")]))
+}
+
+#[tokio::test]
+async fn binary_content_passes_through_untouched() {
+    // Not valid UTF-8, and contains bytes a lossy decode would replace.
+    let pdf = b"%PDF-1.7\n\xff\xfe secret <script>\x00".to_vec();
+    let storage = MetadataStorage::new();
+    let handle =
+        script_and_secret_sanitizers(HtmlSaverBuilder::new(storage.clone())).build::<Payload>();
+    handle
+        .save(Payload {
+            name: "doc.pdf",
+            content_type: "application/pdf",
+            data: pdf.clone(),
+        })
+        .unwrap();
+    handle.shutdown().await;
+
+    let files = objects(&storage.inner);
+    assert_eq!(*files, vec![("doc.pdf".to_string(), pdf)]);
+    let (_, metadata) = storage.metadata.lock().await[0].clone();
+    assert_eq!(metadata.content_type, "application/pdf");
+}
+
+#[tokio::test]
+async fn text_sanitizers_run_only_on_types_they_apply_to() {
+    let storage = MetadataStorage::new();
+    let handle =
+        script_and_secret_sanitizers(HtmlSaverBuilder::new(storage.clone())).build::<Payload>();
+    handle
+        .save(Payload {
+            name: "data.json",
+            content_type: "application/json",
+            data: br#"{"token":"secret","html":"<script></script>"}"#.to_vec(),
+        })
+        .unwrap();
+    handle.shutdown().await;
+
+    let files = objects(&storage.inner);
+    assert_eq!(
+        String::from_utf8_lossy(&files[0].1),
+        r#"{"token":"***","html":"<script></script>"}"#
+    );
+}
+
+#[tokio::test]
+async fn binary_content_survives_wal_replay() {
+    let wal = TempDir::new().unwrap();
+    let storage = MemoryStorage::new();
+    let png = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+
+    let handle = HtmlSaverBuilder::new(StalledStorage)
+        .write_ahead_log(WalConfig::new(wal.path()))
+        .build::<Payload>();
+    handle
+        .save(Payload {
+            name: "logo.png",
+            content_type: "image/png",
+            data: png.clone(),
+        })
+        .unwrap();
+    drop(handle);
+
+    let handle = HtmlSaverBuilder::new(storage.clone())
+        .write_ahead_log(WalConfig::new(wal.path()))
+        .add_sanitizer(SubstringSanitizer::new(vec![("PNG", "GIF")]))
+        .build::<Payload>();
+    handle.shutdown().await;
+
+    let files = objects(&storage);
+    assert_eq!(*files, vec![("logo.png".to_string(), png)]);
+}
+
+// ---------------------------------------------------------------------------
+// Google Cloud Storage
+// ---------------------------------------------------------------------------
+
+/// Round trip through fake-gcs-server, e.g.
+/// `docker run -p 4443:4443 fsouza/fake-gcs-server -scheme http` with
+/// `STORAGE_EMULATOR_HOST=localhost:4443`.
+#[cfg(feature = "gcs")]
+#[tokio::test]
+#[ignore = "requires fake-gcs-server at STORAGE_EMULATOR_HOST"]
+async fn gcs_emulator_round_trip() {
+    let host = std::env::var("STORAGE_EMULATOR_HOST").unwrap();
+    let host = if host.contains("://") {
+        host
+    } else {
+        format!("http://{host}")
+    };
+    let bucket = "html-saver-test";
+    let client = reqwest::Client::new();
+    client
+        .post(format!("{host}/storage/v1/b"))
+        .json(&serde_json::json!({ "name": bucket }))
+        .send()
+        .await
+        .unwrap();
+
+    let handle = HtmlSaverBuilder::new(html_saver::GcsStorage::from_env(bucket).await.unwrap())
+        .prefix("pages")
+        .build::<ScrapedPage>();
+    handle.save(scraped_page()).unwrap();
+    handle.shutdown().await;
+
+    let object: serde_json::Value = client
+        .get(format!("{host}/storage/v1/b/{bucket}/o/pages%2Fpage.html"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(object["contentType"], "text/html");
+    assert_eq!(object["metadata"]["source-url"], "https://example.com/");
+
+    let body = client
+        .get(format!(
+            "{host}/storage/v1/b/{bucket}/o/pages%2Fpage.html?alt=media"
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "<p>hi</p>");
+}
+
+// ---------------------------------------------------------------------------
+// Azure Blob Storage
+// ---------------------------------------------------------------------------
+
+/// Upload through Azurite, e.g. `docker run -p 10000:10000
+/// mcr.microsoft.com/azure-storage/azurite azurite-blob --blobHost 0.0.0.0`
+/// with a `pages` container created beforehand.
+#[cfg(feature = "azure")]
+#[tokio::test]
+#[ignore = "requires Azurite on 127.0.0.1:10000 with a `pages` container"]
+async fn azure_azurite_small_and_block_uploads() {
+    let storage =
+        html_saver::AzureBlobStorage::from_connection_string("UseDevelopmentStorage=true", "pages")
+            .unwrap()
+            .block_upload_threshold(8 * 1024 * 1024)
+            .block_size(4 * 1024 * 1024);
+
+    let metadata = scraped_page().metadata();
+    storage
+        .put("small.html", b"<p>hi</p>", &metadata)
+        .await
+        .unwrap();
+    storage
+        .put("large.html", &vec![b'x'; 10 * 1024 * 1024], &metadata)
+        .await
+        .unwrap();
+}
+
+// ---------------------------------------------------------------------------
+// object_store
+// ---------------------------------------------------------------------------
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn object_store_storage_shares_an_existing_store() {
+    use html_saver::object_store::memory::InMemory;
+    use html_saver::object_store::path::Path;
+    use html_saver::object_store::{Attribute, ObjectStore};
+
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let handle = HtmlSaverBuilder::new(html_saver::ObjectStoreStorage::new(store.clone()))
+        .prefix("pages")
+        .build::<ScrapedPage>();
+    handle.save(scraped_page()).unwrap();
+    handle.shutdown().await;
+
+    let object = store.get(&Path::from("pages/page.html")).await.unwrap();
+    assert_eq!(
+        object
+            .attributes
+            .get(&Attribute::Metadata("source-url".into()))
+            .map(|v| v.as_ref()),
+        Some("https://example.com/")
+    );
+    assert_eq!(&object.bytes().await.unwrap()[..], b"<p>hi</p>");
+}